@@ -0,0 +1,13 @@
+// Small helpers for the flags passed after `<day> <input>` on the command line.
+
+pub(crate) fn has_flag(options: &[String], name: &str) -> bool {
+    options.iter().any(|option| option == name)
+}
+
+pub(crate) fn get_option<'a>(options: &'a [String], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .position(|option| option == name)
+        .and_then(|i| options.get(i + 1))
+        .map(String::as_str)
+}
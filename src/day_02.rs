@@ -1,88 +1,588 @@
 // https://adventofcode.com/2022/day/2
 
-use std::{cmp::Ordering, env, fs};
+use std::{cmp::Ordering, collections::HashSet, fmt::Write, fs, str::FromStr};
 
-pub(crate) fn get_player_game(elf: &char, our: &char) -> Ordering {
-    match (elf, our) {
-        ('A', 'X') | ('B', 'Y') | ('C', 'Z') => Ordering::Equal,
-        ('A', 'Y') | ('B', 'Z') | ('C', 'X') => Ordering::Greater,
-        ('A', 'Z') | ('B', 'X') | ('C', 'Y') => Ordering::Less,
-        _ => unreachable!("invalid plays: {} vs {}", elf, our),
+use miette::{miette, IntoDiagnostic, Result};
+
+use crate::cli::{get_option, has_flag};
+
+/// The puzzle's own rules, written in the same format accepted by `--rules`.
+pub(crate) static ROCK_PAPER_SCISSORS: &str = "
+# outcome points
+lose 0
+draw 3
+win 6
+
+# shape <name> <elf symbol> <our symbol> <points>, in cyclic order
+shape Rock A X 1
+shape Paper B Y 2
+shape Scissors C Z 3
+";
+
+pub(crate) struct Shape {
+    name: String,
+    elf: char,
+    our: char,
+    points: u32,
+}
+
+/// An odd number of shapes in a cycle, where each shape beats the `n / 2`
+/// shapes listed before it (wrapping around) and loses to the `n / 2` after it.
+pub(crate) struct Game {
+    shapes: Vec<Shape>,
+    lose: u32,
+    draw: u32,
+    win: u32,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        ROCK_PAPER_SCISSORS
+            .parse()
+            .expect("built-in rules must parse")
     }
 }
 
-pub(crate) fn get_player_play(outcome: &char, elf: &char) -> char {
-    match (outcome, elf) {
-        ('X', 'A') | ('Y', 'C') | ('Z', 'B') => 'Z',
-        ('X', 'B') | ('Y', 'A') | ('Z', 'C') => 'X',
-        ('X', 'C') | ('Y', 'B') | ('Z', 'A') => 'Y',
-        _ => unreachable!("invalid plays: {} vs {}", outcome, elf),
+fn parse_symbol(s: &str, i: usize) -> Result<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(miette!(
+            "line {}: expected a single char symbol: {:?}",
+            i + 1,
+            s
+        )),
     }
 }
 
-pub(crate) fn get_game_points(elf: &char, our: &char) -> u32 {
-    match get_player_game(elf, our) {
-        Ordering::Less => 0,
-        Ordering::Equal => 3,
-        Ordering::Greater => 6,
+fn parse_points(s: &str, i: usize) -> Result<u32> {
+    s.parse::<u32>()
+        .map_err(|e| miette!("{:?} - line {}: {:?}", e, i + 1, s))
+}
+
+fn ensure_unique<T, I>(values: I, what: &str) -> Result<()>
+where
+    T: Eq + std::hash::Hash,
+    I: ExactSizeIterator<Item = T>,
+{
+    let len = values.len();
+    if values.collect::<HashSet<_>>().len() != len {
+        return Err(miette!("shape {}s must be unique", what));
     }
+    Ok(())
 }
 
-pub(crate) fn get_play_points(our: &char) -> u32 {
-    match our {
-        'X' => 1,
-        'Y' => 2,
-        'Z' => 3,
-        _ => unreachable!("invalid play: {}", our),
+impl FromStr for Game {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut shapes = vec![];
+        let (mut lose, mut draw, mut win) = (None, None, None);
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                ["lose", points] => lose = Some(parse_points(points, i)?),
+                ["draw", points] => draw = Some(parse_points(points, i)?),
+                ["win", points] => win = Some(parse_points(points, i)?),
+                ["shape", name, elf, our, points] => shapes.push(Shape {
+                    name: name.to_string(),
+                    elf: parse_symbol(elf, i)?,
+                    our: parse_symbol(our, i)?,
+                    points: parse_points(points, i)?,
+                }),
+                _ => return Err(miette!("line {}: unrecognized rule: {:?}", i + 1, line)),
+            }
+        }
+
+        if shapes.len() % 2 == 0 {
+            return Err(miette!(
+                "expected an odd number of shapes, found {}",
+                shapes.len()
+            ));
+        }
+        ensure_unique(shapes.iter().map(|shape| &shape.name), "name")?;
+        ensure_unique(shapes.iter().map(|shape| shape.elf), "elf symbol")?;
+        ensure_unique(shapes.iter().map(|shape| shape.our), "our symbol")?;
+
+        Ok(Self {
+            shapes,
+            lose: lose.ok_or_else(|| miette!("missing points for `lose`"))?,
+            draw: draw.ok_or_else(|| miette!("missing points for `draw`"))?,
+            win: win.ok_or_else(|| miette!("missing points for `win`"))?,
+        })
     }
 }
 
-pub(crate) fn score_game(elf: &char, our: &char) -> u32 {
-    get_game_points(elf, our) + get_play_points(our)
+impl Game {
+    fn elf_shape(&self, elf: &char) -> Result<usize> {
+        self.shapes
+            .iter()
+            .position(|shape| shape.elf == *elf)
+            .ok_or_else(|| miette!("no shape for elf symbol {:?}", elf))
+    }
+
+    fn our_shape(&self, our: &char) -> Result<usize> {
+        self.shapes
+            .iter()
+            .position(|shape| shape.our == *our)
+            .ok_or_else(|| miette!("no shape for our symbol {:?}", our))
+    }
+
+    fn elf_name(&self, elf: &char) -> Result<&str> {
+        Ok(&self.shapes[self.elf_shape(elf)?].name)
+    }
+
+    fn our_name(&self, our: &char) -> Result<&str> {
+        Ok(&self.shapes[self.our_shape(our)?].name)
+    }
+
+    fn outcome(&self, elf: usize, our: usize) -> Ordering {
+        let n = self.shapes.len();
+        match (our + n - elf) % n {
+            0 => Ordering::Equal,
+            distance if distance <= n / 2 => Ordering::Greater,
+            _ => Ordering::Less,
+        }
+    }
 }
 
-pub(crate) fn parse_file(file: &str) -> Vec<(char, char)> {
+pub(crate) fn get_player_game(game: &Game, elf: &char, our: &char) -> Result<Ordering> {
+    Ok(game.outcome(game.elf_shape(elf)?, game.our_shape(our)?))
+}
+
+/// Reads our column as the wanted result: the middle symbol draws, and each
+/// symbol either side of it picks a shape one step further round the cycle.
+pub(crate) fn get_player_play(game: &Game, outcome: &char, elf: &char) -> Result<char> {
+    let n = game.shapes.len();
+    let offset = game.our_shape(outcome)? + n - n / 2;
+    Ok(game.shapes[(game.elf_shape(elf)? + offset) % n].our)
+}
+
+pub(crate) fn get_game_points(game: &Game, elf: &char, our: &char) -> Result<u32> {
+    Ok(match get_player_game(game, elf, our)? {
+        Ordering::Less => game.lose,
+        Ordering::Equal => game.draw,
+        Ordering::Greater => game.win,
+    })
+}
+
+pub(crate) fn get_play_points(game: &Game, our: &char) -> Result<u32> {
+    Ok(game.shapes[game.our_shape(our)?].points)
+}
+
+pub(crate) fn score_game(game: &Game, elf: &char, our: &char) -> Result<u32> {
+    Ok(get_game_points(game, elf, our)? + get_play_points(game, our)?)
+}
+
+pub(crate) fn parse_file(file: &str) -> Result<Vec<(char, char)>> {
     file.lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(i, line)| {
             let mut chars = line.chars();
-            let elf = chars.next().unwrap();
-            chars.next();
-            let our = chars.next().unwrap();
-            (elf, our)
+            match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                (Some(elf), Some(' '), Some(our), None) => Ok((elf, our)),
+                _ => Err(miette!(
+                    "line {}: expected `<elf> <our>`: {:?}",
+                    i + 1,
+                    line
+                )),
+            }
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
-pub(crate) fn collect_scores(games: &Vec<(char, char)>) -> Vec<u32> {
+/// Fails on the first round that uses a symbol the rules don't define.
+pub(crate) fn check_rounds(game: &Game, games: &[(char, char)]) -> Result<()> {
+    for (i, (elf, our)) in games.iter().enumerate() {
+        game.elf_shape(elf)
+            .and_then(|_| game.our_shape(our))
+            .map_err(|e| miette!("{} - line {}: \"{} {}\"", e, i + 1, elf, our))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn collect_scores(game: &Game, games: &[(char, char)]) -> Result<Vec<u32>> {
     games
         .iter()
-        .map(|(elf, our)| score_game(elf, our))
+        .map(|(elf, our)| score_game(game, elf, our))
         .collect()
 }
 
-pub(crate) fn part_1_and_2() -> String {
-    let games = env::args()
-        .nth(1)
-        .map(fs::read_to_string)
-        .expect("failed to read input file")
-        .as_deref()
-        .map(parse_file)
-        .expect("failed to parse scores");
+/// Every shape equally likely, indexed like `Game::shapes`.
+pub(crate) fn uniform_opponent(game: &Game) -> Vec<f64> {
+    vec![1.0 / game.shapes.len() as f64; game.shapes.len()]
+}
+
+/// Each shape weighted by how often the elf plays it in the guide. An empty
+/// guide gives every shape a weight of zero.
+pub(crate) fn frequency_opponent(game: &Game, games: &[(char, char)]) -> Result<Vec<f64>> {
+    let mut counts = vec![0.0; game.shapes.len()];
+    if games.is_empty() {
+        return Ok(counts);
+    }
+    for (elf, _) in games {
+        counts[game.elf_shape(elf)?] += 1.0;
+    }
+    Ok(counts
+        .iter()
+        .map(|count| count / games.len() as f64)
+        .collect())
+}
+
+pub(crate) fn get_expected_score(game: &Game, opponent: &[f64], our: &char) -> Result<f64> {
+    game.shapes
+        .iter()
+        .zip(opponent)
+        .map(|(shape, p)| Ok(p * score_game(game, &shape.elf, our)? as f64))
+        .sum()
+}
+
+pub(crate) fn get_best_play(game: &Game, elf: &char) -> Result<char> {
+    let scores = game
+        .shapes
+        .iter()
+        .map(|shape| Ok((shape.our, score_game(game, elf, &shape.our)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(scores
+        .into_iter()
+        .max_by_key(|&(_, score)| score)
+        .expect("expected at least one shape")
+        .0)
+}
 
-    let part_1_scores: u32 = collect_scores(&games).iter().sum();
+pub(crate) fn get_best_expected_play(game: &Game, opponent: &[f64]) -> Result<(char, f64)> {
+    let scores = game
+        .shapes
+        .iter()
+        .map(|shape| Ok((shape.our, get_expected_score(game, opponent, &shape.our)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(scores
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .expect("expected at least one shape"))
+}
+
+pub(crate) struct RoundAnalysis {
+    elf: char,
+    our: char,
+    score: u32,
+    best: char,
+    best_score: u32,
+    uniform: f64,
+    frequency: f64,
+}
+
+/// Scores each round of the guide (our column read as a shape) against the
+/// best response to the elf's play, and against the two opponent estimates.
+pub(crate) fn analyze_rounds(game: &Game, games: &[(char, char)]) -> Result<Vec<RoundAnalysis>> {
+    let uniform = uniform_opponent(game);
+    let frequency = frequency_opponent(game, games)?;
+
+    games
+        .iter()
+        .map(|(elf, our)| {
+            let best = get_best_play(game, elf)?;
+            Ok(RoundAnalysis {
+                elf: *elf,
+                our: *our,
+                score: score_game(game, elf, our)?,
+                best,
+                best_score: score_game(game, elf, &best)?,
+                uniform: get_expected_score(game, &uniform, our)?,
+                frequency: get_expected_score(game, &frequency, our)?,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn format_analysis(game: &Game, games: &[(char, char)]) -> Result<String> {
+    let rounds = analyze_rounds(game, games)?;
+    let mut table = format!(
+        "{:>6} {:>3} {:>3} {:>5} {:>4} {:>10} {:>6} {:>10} {:>10}\n",
+        "round", "elf", "our", "score", "best", "best score", "regret", "E[uniform]", "E[freq]"
+    );
+
+    for (i, round) in rounds.iter().enumerate() {
+        writeln!(
+            table,
+            "{:>6} {:>3} {:>3} {:>5} {:>4} {:>10} {:>6} {:>10.2} {:>10.2}",
+            i + 1,
+            round.elf,
+            round.our,
+            round.score,
+            round.best,
+            round.best_score,
+            round.best_score - round.score,
+            round.uniform,
+            round.frequency,
+        )
+        .unwrap();
+    }
+
+    let score: u32 = rounds.iter().map(|round| round.score).sum();
+    let best_score: u32 = rounds.iter().map(|round| round.best_score).sum();
+    let uniform: f64 = rounds.iter().map(|round| round.uniform).sum();
+    let frequency: f64 = rounds.iter().map(|round| round.frequency).sum();
+
+    writeln!(
+        table,
+        "{:>6} {:>3} {:>3} {:>5} {:>4} {:>10} {:>6} {:>10.2} {:>10.2}",
+        "total",
+        "",
+        "",
+        score,
+        "",
+        best_score,
+        best_score - score,
+        uniform,
+        frequency,
+    )
+    .unwrap();
+
+    let (uniform_play, uniform_score) = get_best_expected_play(game, &uniform_opponent(game))?;
+    let (frequency_play, frequency_score) =
+        get_best_expected_play(game, &frequency_opponent(game, games)?)?;
+
+    Ok(format!(
+        r#"
+{}
+Supplied strategy: {} of an optimal {} ({:.1}%), {} points short
+Best fixed play vs uniform opponent: {} ({:.2} per round, {:.2} total)
+Best fixed play vs frequency-estimated opponent: {} ({:.2} per round, {:.2} total)
+"#,
+        table,
+        score,
+        best_score,
+        100.0 * score as f64 / best_score.max(1) as f64,
+        best_score - score,
+        uniform_play,
+        uniform_score,
+        uniform_score * rounds.len() as f64,
+        frequency_play,
+        frequency_score,
+        frequency_score * rounds.len() as f64,
+    ))
+}
+
+fn format_outcome(outcome: Ordering) -> &'static str {
+    match outcome {
+        Ordering::Less => "lose",
+        Ordering::Equal => "draw",
+        Ordering::Greater => "win",
+    }
+}
+
+/// One line per round with both readings of our column side by side: part 1
+/// plays it as a shape, part 2 plays whatever reaches it as an outcome.
+pub(crate) fn format_trace(game: &Game, games: &[(char, char)]) -> Result<String> {
+    let mut trace = String::new();
+    let (mut part_1_total, mut part_2_total) = (0, 0);
+
+    for (i, (elf, column)) in games.iter().enumerate() {
+        write!(trace, "{:>5} {} {:<10}", i + 1, elf, game.elf_name(elf)?).unwrap();
+
+        let part_2_play = get_player_play(game, column, elf)?;
+        for (our, total) in [
+            (*column, &mut part_1_total),
+            (part_2_play, &mut part_2_total),
+        ] {
+            let game_points = get_game_points(game, elf, &our)?;
+            let play_points = get_play_points(game, &our)?;
+            *total += game_points + play_points;
+
+            write!(
+                trace,
+                " | {} {:<10} {:<4} {:>2} + {:>2} = {:>2} ({:>6})",
+                our,
+                game.our_name(&our)?,
+                format_outcome(get_player_game(game, elf, &our)?),
+                game_points,
+                play_points,
+                game_points + play_points,
+                total,
+            )
+            .unwrap();
+        }
+
+        trace.push('\n');
+    }
+
+    Ok(format!(
+        r#"
+round elf          | part 1: play as shape                   | part 2: play for outcome
+{}
+Part 1: Total game score: {}
+Part 2: Total game score: {}
+"#,
+        trace, part_1_total, part_2_total,
+    ))
+}
+
+pub(crate) fn part_1_and_2(input: String, options: &[String]) -> Result<String> {
+    let game = match get_option(options, "--rules") {
+        Some(path) => fs::read_to_string(path).into_diagnostic()?.parse()?,
+        None => Game::default(),
+    };
+
+    let games = parse_file(&input)?;
+    check_rounds(&game, &games)?;
+
+    if has_flag(options, "--analyze") {
+        return format_analysis(&game, &games);
+    }
+
+    if has_flag(options, "--trace") {
+        return format_trace(&game, &games);
+    }
+
+    let part_1_scores: u32 = collect_scores(&game, &games)?.iter().sum();
 
     let games = games
         .into_iter()
-        .map(|(elf, outcome)| (elf, get_player_play(&outcome, &elf)))
-        .collect::<Vec<_>>();
+        .map(|(elf, outcome)| Ok((elf, get_player_play(&game, &outcome, &elf)?)))
+        .collect::<Result<Vec<_>>>()?;
 
-    let part_2_scores: u32 = collect_scores(&games).iter().sum();
+    let part_2_scores: u32 = collect_scores(&game, &games)?.iter().sum();
 
-    format!(
+    Ok(format!(
         r#"
 Part 1: Total game score: {}
 Part 2: Total game score: {}
 "#,
         part_1_scores, part_2_scores,
-    )
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::*;
+
+    static INPUT: &str = "A Y
+B X
+C Z";
+
+    static ROCK_PAPER_SCISSORS_LIZARD_SPOCK: &str = "
+lose 0
+draw 3
+win 6
+shape Rock A V 1
+shape Spock B W 2
+shape Paper C X 3
+shape Lizard D Y 4
+shape Scissors E Z 5
+";
+
+    #[rstest(input, expected, case(INPUT, 15))]
+    fn test_part_1(input: &str, expected: u32) {
+        let game = Game::default();
+        let scores = collect_scores(&game, &parse_file(input).unwrap()).unwrap();
+        assert_eq!(scores.iter().sum::<u32>(), expected);
+    }
+
+    #[rstest(input, expected, case(INPUT, 12))]
+    fn test_part_2(input: &str, expected: u32) {
+        let game = Game::default();
+        let games = parse_file(input)
+            .unwrap()
+            .into_iter()
+            .map(|(elf, outcome)| (elf, get_player_play(&game, &outcome, &elf).unwrap()))
+            .collect::<Vec<_>>();
+        let scores = collect_scores(&game, &games).unwrap();
+        assert_eq!(scores.iter().sum::<u32>(), expected);
+    }
+
+    #[rstest(input, expected, case(INPUT, "    3 C Scissors   | Z Scissors   draw  3 +  3 =  6 (    15) | X Rock       win   6 +  1 =  7 (    12)"))]
+    fn test_trace(input: &str, expected: &str) {
+        let trace = format_trace(&Game::default(), &parse_file(input).unwrap()).unwrap();
+        assert!(trace.lines().any(|line| line == expected), "{}", trace);
+    }
+
+    #[rstest(input, expected, case(INPUT, (15, 24)))]
+    fn test_analyze_rounds(input: &str, expected: (u32, u32)) {
+        let rounds = analyze_rounds(&Game::default(), &parse_file(input).unwrap()).unwrap();
+        let score = rounds.iter().map(|round| round.score).sum();
+        let best_score = rounds.iter().map(|round| round.best_score).sum();
+        assert_eq!((score, best_score), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(INPUT, 15.0),
+        case("A X\nA Y\nB Z", 15.0),
+        case("A Y\nA Y", 16.0)
+    )]
+    fn test_expected_frequency(input: &str, expected: f64) {
+        let rounds = analyze_rounds(&Game::default(), &parse_file(input).unwrap()).unwrap();
+        let frequency: f64 = rounds.iter().map(|round| round.frequency).sum();
+        assert!((frequency - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_empty_guide() {
+        let game = Game::default();
+        assert_eq!(frequency_opponent(&game, &[]).unwrap(), [0.0; 3]);
+        let analysis = format_analysis(&game, &[]).unwrap();
+        assert!(!analysis.contains("NaN"), "{}", analysis);
+    }
+
+    #[rstest(
+        elf,
+        our,
+        expected,
+        case('A', 'Y', Ordering::Less),    // rock crushes lizard
+        case('A', 'W', Ordering::Greater), // spock vaporizes rock
+        case('B', 'Z', Ordering::Less),    // spock smashes scissors
+        case('C', 'W', Ordering::Less),    // paper disproves spock
+        case('D', 'W', Ordering::Less),    // lizard poisons spock
+        case('D', 'X', Ordering::Less),    // lizard eats paper
+        case('E', 'Y', Ordering::Less),    // scissors decapitates lizard
+        case('E', 'V', Ordering::Greater), // rock crushes scissors
+        case('C', 'X', Ordering::Equal)
+    )]
+    fn test_lizard_spock_game(elf: char, our: char, expected: Ordering) {
+        let game = ROCK_PAPER_SCISSORS_LIZARD_SPOCK.parse::<Game>().unwrap();
+        assert_eq!(get_player_game(&game, &elf, &our).unwrap(), expected);
+    }
+
+    #[rstest(
+        outcome,
+        elf,
+        expected,
+        case('V', 'A', 'Y'),
+        case('W', 'A', 'Z'),
+        case('X', 'A', 'V'),
+        case('Y', 'A', 'W'),
+        case('Z', 'A', 'X')
+    )]
+    fn test_lizard_spock_play(outcome: char, elf: char, expected: char) {
+        let game = ROCK_PAPER_SCISSORS_LIZARD_SPOCK.parse::<Game>().unwrap();
+        assert_eq!(get_player_play(&game, &outcome, &elf).unwrap(), expected);
+    }
+
+    #[rstest(
+        rules,
+        case("lose 0\ndraw 3\nwin 6\nshape Rock A X 1\nshape Paper B Y 2"),
+        case("lose 0\ndraw 3\nshape Rock A X 1"),
+        case("lose 0\ndraw 3\nwin 6\nshape Rock A X 1\nshape Paper B X 2\nshape Scissors C Z 3"),
+        case("lose 0\ndraw 3\nwin six\nshape Rock A X 1")
+    )]
+    fn test_invalid_rules(rules: &str) {
+        assert!(rules.parse::<Game>().is_err());
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("A Y\nD X", "no shape for elf symbol 'D' - line 2: \"D X\""),
+        case("A W", "no shape for our symbol 'W' - line 1: \"A W\""),
+        case("A Y\nAY", "line 2: expected `<elf> <our>`: \"AY\"")
+    )]
+    fn test_unknown_symbols(input: &str, expected: &str) {
+        let error = part_1_and_2(input.to_string(), &[]).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
 }
@@ -1,84 +1,434 @@
-// https://adventofcode.com/2022/day/2
+// https://adventofcode.com/2022/day/3
 
-use std::{collections::HashSet, env, fs};
+use std::{fmt::Display, iter::FromIterator, ops::BitAnd};
+
+use miette::{miette, IntoDiagnostic, Result};
+
+use crate::cli::{get_option, has_flag};
+
+/// The 52 item types packed one bit each, with bit `n` set for the item of
+/// priority `n` (`a`-`z` are 1-26, `A`-`Z` are 27-52).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ItemSet(u64);
+
+impl ItemSet {
+    pub(crate) fn priority(item: char) -> Option<u32> {
+        match item {
+            'a'..='z' => Some(item as u32 - 'a' as u32 + 1),
+            'A'..='Z' => Some(item as u32 - 'A' as u32 + 27),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn item(priority: u32) -> char {
+        match priority {
+            1..=26 => (b'a' + priority as u8 - 1) as char,
+            27..=52 => (b'A' + priority as u8 - 27) as char,
+            _ => panic!("invalid priority: {}", priority),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, item: char) {
+        let priority = Self::priority(item).unwrap_or_else(|| panic!("invalid item: {:?}", item));
+        self.0 |= 1 << priority;
+    }
+
+    pub(crate) fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Priorities of the items in the set, lowest first.
+    pub(crate) fn priorities(self) -> impl Iterator<Item = u32> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let priority = bits.trailing_zeros();
+            bits &= bits - 1;
+            Some(priority)
+        })
+    }
+}
+
+impl FromIterator<char> for ItemSet {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut set = Self::default();
+        iter.into_iter().for_each(|item| set.insert(item));
+        set
+    }
+}
+
+impl From<&str> for ItemSet {
+    fn from(items: &str) -> Self {
+        items.chars().collect()
+    }
+}
+
+impl BitAnd for ItemSet {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+/// Splits a rucksack into its two compartments, counting in characters so a
+/// stray multi-byte character can't land on the split.
+fn split_compartments(line: &str) -> (&str, &str) {
+    let half = line.chars().count() / 2;
+    let mid = line.char_indices().nth(half).map_or(line.len(), |(i, _)| i);
+    line.split_at(mid)
+}
 
 pub(crate) fn parse_file(file: &str) -> Vec<(&str, &str)> {
-    file.lines()
-        .map(|line| line.split_at(line.len() / 2))
-        .collect::<Vec<_>>()
+    file.lines().map(split_compartments).collect::<Vec<_>>()
 }
 
-pub(crate) fn parse_file_part_2(file: &str) -> Vec<Vec<&str>> {
+pub(crate) fn parse_file_part_2(file: &str, group_size: usize) -> Vec<Vec<&str>> {
     file.lines()
         .collect::<Vec<_>>()
-        .chunks(3)
+        .chunks(group_size)
         .map(|chunk| chunk.to_vec())
         .collect()
 }
 
-pub(crate) fn find_repeat_chars(row: (&str, &str)) -> HashSet<char> {
-    let l = row.0.chars().collect::<HashSet<_>>();
-    let r = row.1.chars().collect::<HashSet<_>>();
-    l.intersection(&r).copied().collect()
+pub(crate) fn find_repeat_chars(row: (&str, &str)) -> ItemSet {
+    ItemSet::from(row.0) & ItemSet::from(row.1)
 }
 
-pub(crate) fn find_repeat_chars_part_2(group: Vec<&str>) -> HashSet<char> {
+pub(crate) fn find_repeat_chars_part_2(group: &[&str]) -> ItemSet {
     group
-        .into_iter()
-        .map(|line| line.chars().collect::<HashSet<_>>())
-        .fold(None, |acc: Option<HashSet<char>>, next| match acc {
-            None => Some(next),
-            Some(curr) => Some(curr.intersection(&next).copied().collect()),
-        })
+        .iter()
+        .map(|&line| ItemSet::from(line))
+        .reduce(BitAnd::bitand)
         .expect("expected at least one group member")
 }
 
-pub(crate) fn score_char(c: char) -> u32 {
-    let v = c.to_digit(36).expect("must be a valid digit");
-    v - 9 + (if c.is_uppercase() { 26 } else { 0 })
+pub(crate) fn score_first_repeat(repeats: ItemSet) -> u32 {
+    repeats.priorities().next().unwrap_or_default()
 }
 
-pub(crate) fn score_first_repeat(repeats: HashSet<char>) -> u32 {
-    repeats
-        .into_iter()
-        .next()
-        .map(score_char)
-        .unwrap_or_default()
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum IssueKind {
+    OddLength { len: usize },
+    InvalidItem { item: char, column: usize },
+    NoSharedItem,
+    MultipleSharedItems { items: String },
+    IncompleteGroup { size: usize, group_size: usize },
+    NoGroupBadge,
+    MultipleGroupBadges { items: String },
+}
+
+/// A problem found by `validate`, located at its 1-based line (for group
+/// problems, the first line of the group).
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Issue {
+    line: usize,
+    kind: IssueKind,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            IssueKind::OddLength { len } => {
+                write!(f, "odd length {} can't split into equal compartments", len)
+            }
+            IssueKind::InvalidItem { item, column } => {
+                write!(f, "column {}: {:?} is not an item letter", column, item)
+            }
+            IssueKind::NoSharedItem => write!(f, "compartments share no item"),
+            IssueKind::MultipleSharedItems { items } => {
+                write!(f, "compartments share several items: {}", items)
+            }
+            IssueKind::IncompleteGroup { size, group_size } => {
+                write!(f, "last group has {} of {} members", size, group_size)
+            }
+            IssueKind::NoGroupBadge => write!(f, "group shares no badge item"),
+            IssueKind::MultipleGroupBadges { items } => {
+                write!(f, "group shares several badge items: {}", items)
+            }
+        }
+    }
+}
+
+fn valid_items(line: &str) -> ItemSet {
+    line.chars()
+        .filter(|&item| ItemSet::priority(item).is_some())
+        .collect()
+}
+
+fn shared_item_issue(line: usize, shared: ItemSet, group: bool) -> Option<Issue> {
+    let items = || shared.priorities().map(ItemSet::item).collect::<String>();
+    let kind = match (shared.len(), group) {
+        (1, _) => return None,
+        (0, false) => IssueKind::NoSharedItem,
+        (0, true) => IssueKind::NoGroupBadge,
+        (_, false) => IssueKind::MultipleSharedItems { items: items() },
+        (_, true) => IssueKind::MultipleGroupBadges { items: items() },
+    };
+    Some(Issue { line, kind })
+}
+
+/// Checks everything `parse_file` and `parse_file_part_2` take on trust.
+pub(crate) fn validate(file: &str, group_size: usize) -> Vec<Issue> {
+    let mut issues = vec![];
+    let lines = file.lines().collect::<Vec<_>>();
+
+    for (i, line) in lines.iter().enumerate() {
+        let len = line.chars().count();
+        if len % 2 != 0 {
+            issues.push(Issue {
+                line: i + 1,
+                kind: IssueKind::OddLength { len },
+            });
+        }
+
+        for (column, item) in line.chars().enumerate() {
+            if ItemSet::priority(item).is_none() {
+                issues.push(Issue {
+                    line: i + 1,
+                    kind: IssueKind::InvalidItem {
+                        item,
+                        column: column + 1,
+                    },
+                });
+            }
+        }
+
+        let (l, r) = split_compartments(line);
+        issues.extend(shared_item_issue(
+            i + 1,
+            valid_items(l) & valid_items(r),
+            false,
+        ));
+    }
+
+    for (i, group) in lines.chunks(group_size).enumerate() {
+        let line = i * group_size + 1;
+        if group.len() < group_size {
+            issues.push(Issue {
+                line,
+                kind: IssueKind::IncompleteGroup {
+                    size: group.len(),
+                    group_size,
+                },
+            });
+        }
+
+        let badges = group
+            .iter()
+            .map(|&line| valid_items(line))
+            .reduce(BitAnd::bitand)
+            .unwrap_or_default();
+        issues.extend(shared_item_issue(line, badges, true));
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+pub(crate) fn format_issues(issues: &[Issue]) -> String {
+    if issues.is_empty() {
+        return "\nNo issues found\n".to_string();
+    }
+
+    let lines = issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("\n{} issue(s) found:\n{}\n", issues.len(), lines)
 }
 
-pub(crate) fn part_1() -> String {
-    let input = env::args()
-        .nth(1)
-        .map(fs::read_to_string)
-        .expect("failed to read input file");
+pub(crate) fn part_1(input: String, options: &[String]) -> Result<String> {
+    let group_size = match get_option(options, "--group-size")
+        .map(str::parse::<usize>)
+        .transpose()
+        .into_diagnostic()?
+    {
+        Some(0) => return Err(miette!("--group-size must be at least 1")),
+        group_size => group_size.unwrap_or(3),
+    };
 
-    let data_part_1 = input
-        .as_deref()
-        .map(parse_file)
-        .expect("failed to parse scores");
+    if has_flag(options, "--validate") {
+        return Ok(format_issues(&validate(&input, group_size)));
+    }
 
-    let part_1_score: u32 = data_part_1
+    let part_1_score: u32 = parse_file(&input)
         .into_iter()
         .map(find_repeat_chars)
         .map(score_first_repeat)
         .sum();
 
-    let data_part_2 = input
-        .as_deref()
-        .map(parse_file_part_2)
-        .expect("failed to parse scores");
-
-    let part_2_score: u32 = data_part_2
-        .into_iter()
-        .map(find_repeat_chars_part_2)
+    let part_2_score: u32 = parse_file_part_2(&input, group_size)
+        .iter()
+        .map(|group| find_repeat_chars_part_2(group))
         .map(score_first_repeat)
         .sum();
 
-    format!(
+    Ok(format!(
         r#"
 Part 1: Total game score: {}
 Part 2: Total game score: {}
 "#,
         part_1_score, part_2_score,
-    )
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    extern crate test;
+
+    use super::*;
+    use crate::rng::XorShift;
+    use rstest::*;
+    use std::collections::HashSet;
+    use test::Bencher;
+
+    static INPUT: &str = "vJrwpWtwJgWrhcsFMMfFFhFp
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+PmmdzqPrVvPwwTWBwg
+wMqvLMZHhHMvwLHjbvcjnjSBnvTQFn
+ttgJtRGJQctTZtZT
+CrZsJsPPZsGzwwsLwLmpwMDw";
+
+    #[rstest(input, expected, case(INPUT, 157))]
+    fn test_part_1(input: &str, expected: u32) {
+        let score: u32 = parse_file(input)
+            .into_iter()
+            .map(find_repeat_chars)
+            .map(score_first_repeat)
+            .sum();
+        assert_eq!(score, expected);
+    }
+
+    #[rstest(input, group_size, expected, case(INPUT, 3, 70), case(INPUT, 2, 56))]
+    fn test_part_2(input: &str, group_size: usize, expected: u32) {
+        let score: u32 = parse_file_part_2(input, group_size)
+            .iter()
+            .map(|group| find_repeat_chars_part_2(group))
+            .map(score_first_repeat)
+            .sum();
+        assert_eq!(score, expected);
+    }
+
+    #[rstest(
+        item,
+        expected,
+        case('a', Some(1)),
+        case('z', Some(26)),
+        case('A', Some(27)),
+        case('Z', Some(52)),
+        case('1', None)
+    )]
+    fn test_priority(item: char, expected: Option<u32>) {
+        assert_eq!(ItemSet::priority(item), expected);
+    }
+
+    #[test]
+    fn test_zero_group_size() {
+        let options = ["--group-size".to_string(), "0".to_string()];
+        assert_eq!(
+            part_1(INPUT.to_string(), &options).unwrap_err().to_string(),
+            "--group-size must be at least 1"
+        );
+    }
+
+    #[rstest(input, expected, case(INPUT, 0))]
+    fn test_validate_clean(input: &str, expected: usize) {
+        assert_eq!(validate(input, 3).len(), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("cdeA\nabab\naab\nba1a", vec![
+            Issue { line: 1, kind: IssueKind::NoSharedItem },
+            Issue { line: 1, kind: IssueKind::NoGroupBadge },
+            Issue { line: 2, kind: IssueKind::MultipleSharedItems { items: "ab".to_string() } },
+            Issue { line: 3, kind: IssueKind::OddLength { len: 3 } },
+            Issue { line: 4, kind: IssueKind::InvalidItem { item: '1', column: 3 } },
+            Issue { line: 4, kind: IssueKind::IncompleteGroup { size: 1, group_size: 3 } },
+            Issue { line: 4, kind: IssueKind::MultipleGroupBadges { items: "ab".to_string() } },
+        ]),
+        case("éab", vec![
+            Issue { line: 1, kind: IssueKind::OddLength { len: 3 } },
+            Issue { line: 1, kind: IssueKind::InvalidItem { item: 'é', column: 1 } },
+            Issue { line: 1, kind: IssueKind::NoSharedItem },
+            Issue { line: 1, kind: IssueKind::IncompleteGroup { size: 1, group_size: 3 } },
+            Issue { line: 1, kind: IssueKind::MultipleGroupBadges { items: "ab".to_string() } },
+        ])
+    )]
+    fn test_validate(input: &str, expected: Vec<Issue>) {
+        assert_eq!(validate(input, 3), expected);
+    }
+
+    /// Deterministic rucksacks of `len` random letters, for benchmarking.
+    fn generate_rucksacks(count: usize, len: usize) -> String {
+        let letters = ('a'..='z').chain('A'..='Z').collect::<Vec<_>>();
+        let mut rng = XorShift::default();
+
+        (0..count)
+            .map(|_| {
+                (0..len)
+                    .map(|_| letters[rng.index(letters.len())])
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn hash_set_repeats(group: &[&str]) -> HashSet<char> {
+        group
+            .iter()
+            .map(|line| line.chars().collect::<HashSet<_>>())
+            .reduce(|acc, next| acc.intersection(&next).copied().collect())
+            .unwrap_or_default()
+    }
+
+    #[rstest(group_size, case(1), case(2), case(3), case(5))]
+    fn test_matches_hash_set(group_size: usize) {
+        let input = generate_rucksacks(300, 24);
+        for group in parse_file_part_2(&input, group_size) {
+            let expected = hash_set_repeats(&group);
+            let found = find_repeat_chars_part_2(&group);
+            assert_eq!(found.priorities().count(), expected.len());
+            assert!(expected
+                .into_iter()
+                .all(|c| found.priorities().any(|p| Some(p) == ItemSet::priority(c))));
+        }
+    }
+
+    #[bench]
+    fn bench_hash_set(b: &mut Bencher) {
+        let input = generate_rucksacks(10_000, 48);
+        let groups = parse_file_part_2(&input, 3);
+        b.iter(|| {
+            groups
+                .iter()
+                .map(|group| {
+                    hash_set_repeats(group)
+                        .into_iter()
+                        .filter_map(ItemSet::priority)
+                        .min()
+                        .unwrap_or_default()
+                })
+                .sum::<u32>()
+        });
+    }
+
+    #[bench]
+    fn bench_item_set(b: &mut Bencher) {
+        let input = generate_rucksacks(10_000, 48);
+        let groups = parse_file_part_2(&input, 3);
+        b.iter(|| {
+            groups
+                .iter()
+                .map(|group| score_first_repeat(find_repeat_chars_part_2(group)))
+                .sum::<u32>()
+        });
+    }
 }
@@ -1,51 +1,31 @@
-// https://adventofcode.com/2022/day/2
+// https://adventofcode.com/2022/day/4
 
-use std::{env, fs, num::ParseIntError, str::FromStr};
+use miette::{miette, Result};
 
-pub(crate) struct Assignment {
-    lo: u32,
-    hi: u32,
-}
-
-impl FromStr for Assignment {
-    type Err = ParseIntError;
+use crate::{
+    cli::has_flag,
+    interval::{max_depth, overlapping_pairs, Interval, IntervalSet, Section},
+};
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut ints = s
-            .split('-')
-            .map(|x| x.parse::<u32>().expect("expected int"));
-        Ok(Self {
-            lo: ints.next().expect("expected first low int"),
-            hi: ints.next().expect("expected second high int"),
-        })
-    }
-}
-
-impl Assignment {
-    fn includes(&self, other: &Self) -> bool {
-        self.lo <= other.lo && self.hi >= other.hi
-    }
+pub(crate) type Assignment<T = u32> = Interval<T>;
 
-    fn overlaps(&self, other: &Self) -> bool {
-        self.lo <= other.hi && self.hi >= other.lo
-    }
-}
-
-pub(crate) fn parse_file(file: &str) -> Vec<(Assignment, Assignment)> {
+pub(crate) fn parse_file<T: Section>(file: &str) -> Result<Vec<(Assignment<T>, Assignment<T>)>> {
     file.lines()
-        .map(|line| {
-            let mut assignments = line
-                .split(',')
-                .map(|part| part.parse::<Assignment>().expect("expected assignment"));
-            (
-                assignments.next().expect("expected first assignment"),
-                assignments.next().expect("expected second assignment"),
-            )
+        .enumerate()
+        .map(|(i, line)| {
+            let (l, r) = line
+                .split_once(',')
+                .ok_or_else(|| miette!("line {}: expected two assignments: {:?}", i + 1, line))?;
+            let parse = |part: &str| {
+                part.parse::<Assignment<T>>()
+                    .map_err(|e| miette!("{} - line {}: {:?}", e, i + 1, line))
+            };
+            Ok((parse(l)?, parse(r)?))
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
-pub(crate) fn score_group(assignment: (Assignment, Assignment)) -> u32 {
+pub(crate) fn score_group<T: Section>(assignment: (Assignment<T>, Assignment<T>)) -> u32 {
     let (l, r) = assignment;
     if l.includes(&r) || r.includes(&l) {
         1
@@ -54,7 +34,7 @@ pub(crate) fn score_group(assignment: (Assignment, Assignment)) -> u32 {
     }
 }
 
-pub(crate) fn score_group_part_2(assignment: (Assignment, Assignment)) -> u32 {
+pub(crate) fn score_group_part_2<T: Section>(assignment: (Assignment<T>, Assignment<T>)) -> u32 {
     let (l, r) = assignment;
     if l.overlaps(&r) {
         1
@@ -63,41 +43,195 @@ pub(crate) fn score_group_part_2(assignment: (Assignment, Assignment)) -> u32 {
     }
 }
 
-pub(crate) fn score_all_groups(assignments: Vec<(Assignment, Assignment)>) -> Vec<u32> {
+pub(crate) fn score_all_groups<T: Section>(
+    assignments: Vec<(Assignment<T>, Assignment<T>)>,
+) -> Vec<u32> {
     assignments.into_iter().map(score_group).collect()
 }
 
-pub(crate) fn score_all_groups_part_2(assignments: Vec<(Assignment, Assignment)>) -> Vec<u32> {
+pub(crate) fn score_all_groups_part_2<T: Section>(
+    assignments: Vec<(Assignment<T>, Assignment<T>)>,
+) -> Vec<u32> {
     assignments.into_iter().map(score_group_part_2).collect()
 }
 
-pub(crate) fn solve() -> String {
-    let input = env::args()
-        .nth(1)
-        .map(fs::read_to_string)
-        .expect("failed to read input file");
-
-    let data_part_1 = input.as_deref().map(parse_file);
+/// Every section covered by at least one elf, across all pairs.
+pub(crate) fn get_coverage<T: Section>(
+    assignments: &[(Assignment<T>, Assignment<T>)],
+) -> IntervalSet<T> {
+    assignments.iter().flat_map(|&(l, r)| [l, r]).collect()
+}
 
-    let part_1_score: u32 = data_part_1
-        .map(score_all_groups)
-        .expect("failed to parse scores")
-        .into_iter()
-        .sum();
+pub(crate) fn format_coverage<T: Section>(coverage: &IntervalSet<T>) -> String {
+    format!(
+        r#"
+Sections: {}
+Sections assigned: {} ({})
+Sections unassigned: {} ({})
+"#,
+        coverage
+            .bounds()
+            .map_or_else(|| "none".to_string(), |bounds| bounds.to_string()),
+        coverage.len(),
+        coverage,
+        coverage.gaps().len(),
+        coverage.gaps(),
+    )
+}
 
-    let data_part_2 = input.as_deref().map(parse_file);
+/// Labels each elf by its 1-based line and side, e.g. `3a` for the first elf on line 3.
+fn roster_label(i: usize) -> String {
+    format!("{}{}", i / 2 + 1, ['a', 'b'][i % 2])
+}
 
-    let part_2_score: u32 = data_part_2
-        .map(score_all_groups_part_2)
-        .expect("failed to parse scores")
-        .into_iter()
-        .sum();
+/// Treats every elf on every line as one roster, rather than only comparing
+/// the two elves that share a line.
+pub(crate) fn format_roster<T: Section>(assignments: &[(Assignment<T>, Assignment<T>)]) -> String {
+    let roster = assignments
+        .iter()
+        .flat_map(|&(l, r)| [l, r])
+        .collect::<Vec<_>>();
+
+    let pairs = overlapping_pairs(&roster);
+    let (depth, deepest) = max_depth(&roster);
+
+    let pair_lines = pairs
+        .iter()
+        .map(|&(i, j)| {
+            format!(
+                "{} {} overlaps {} {}",
+                roster_label(i),
+                roster[i],
+                roster_label(j),
+                roster[j]
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
 
     format!(
         r#"
+Assignments: {}
+Overlapping pairs: {}
+Max concurrent assignments: {}
+Most overlapped sections: {}
+{}
+"#,
+        roster.len(),
+        pairs.len(),
+        depth,
+        deepest,
+        pair_lines,
+    )
+}
+
+pub(crate) fn solve(input: String, options: &[String]) -> Result<String> {
+    let assignments = parse_file::<u32>(&input)?;
+
+    if has_flag(options, "--coverage") {
+        return Ok(format_coverage(&get_coverage(&assignments)));
+    }
+
+    if has_flag(options, "--roster") {
+        return Ok(format_roster(&assignments));
+    }
+
+    let part_1_score: u32 = score_all_groups(assignments.clone()).into_iter().sum();
+
+    let part_2_score: u32 = score_all_groups_part_2(assignments).into_iter().sum();
+
+    Ok(format!(
+        r#"
 Part 1: Total game score: {}
 Part 2: Total game score: {}
 "#,
         part_1_score, part_2_score,
-    )
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::*;
+
+    static INPUT: &str = "2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8
+12-13,15-20";
+
+    #[rstest(input, expected, case(INPUT, 2))]
+    fn test_part_1(input: &str, expected: u32) {
+        assert_eq!(
+            score_all_groups(parse_file::<u32>(input).unwrap())
+                .iter()
+                .sum::<u32>(),
+            expected
+        );
+    }
+
+    #[rstest(input, expected, case(INPUT, 4))]
+    fn test_part_2(input: &str, expected: u32) {
+        let scores = score_all_groups_part_2(parse_file::<u32>(input).unwrap());
+        assert_eq!(scores.iter().sum::<u32>(), expected);
+    }
+
+    #[rstest(input, expected, case(INPUT, (49, 8, "6")))]
+    fn test_roster(input: &str, expected: (usize, usize, &str)) {
+        let roster = parse_file::<u32>(input)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(l, r)| [l, r])
+            .collect::<Vec<_>>();
+        let (depth, deepest) = max_depth(&roster);
+        assert_eq!(
+            (overlapping_pairs(&roster).len(), depth, deepest.to_string()),
+            (expected.0, expected.1, expected.2.to_string())
+        );
+    }
+
+    #[test]
+    fn test_large_roster() {
+        let roster = (0..200_000u32)
+            .map(|i| Assignment::new(i * 10, i * 10 + 14))
+            .collect::<Vec<_>>();
+        let (depth, _) = max_depth(&roster);
+        assert_eq!(depth, 2);
+        assert_eq!(overlapping_pairs(&roster).len(), 199_999);
+    }
+
+    #[rstest(input, expected, case(INPUT, ("2-9, 12-13, 15-20", "10-11, 14")))]
+    fn test_coverage(input: &str, expected: (&str, &str)) {
+        let coverage = get_coverage(&parse_file::<u32>(input).unwrap());
+        assert_eq!(
+            (coverage.to_string(), coverage.gaps().to_string()),
+            (expected.0.to_string(), expected.1.to_string())
+        );
+    }
+
+    #[rstest(input, expected, case("-5--1,-3-2", 1), case("-5--1,0-2", 0))]
+    fn test_signed_sections(input: &str, expected: u32) {
+        let scores = score_all_groups_part_2(parse_file::<i64>(input).unwrap());
+        assert_eq!(scores.iter().sum::<u32>(), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(
+            "2-4,6-8\n7-3,1-2",
+            "bounds reversed: 7 is after 3 - line 2: \"7-3,1-2\""
+        ),
+        case("2-4,6-8\n2-4", "line 2: expected two assignments: \"2-4\""),
+        case(
+            "2-4,6-\n",
+            "expected `lo-hi`, `lo..=hi` or `lo..hi`: \"6-\" - line 1: \"2-4,6-\""
+        )
+    )]
+    fn test_parse_errors(input: &str, expected: &str) {
+        let error = parse_file::<u32>(input).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
 }
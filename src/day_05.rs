@@ -1,31 +1,59 @@
 // https://adventofcode.com/2022/day/5
 
-use std::{env, fmt::Display, fs, str::FromStr};
+use std::{
+    collections::HashSet, fmt::Display, fs, io::Write, rc::Rc, str::FromStr, thread, time::Duration,
+};
+
+use miette::{miette, Diagnostic, IntoDiagnostic, Result, SourceSpan};
+
+use crate::cli::{get_option, has_flag};
 
 #[derive(Debug)]
 pub(crate) struct Instruction {
     source: usize,
     target: usize,
     count: usize,
+    /// Where the instruction was in the puzzle input, if it came from one.
+    line: Option<usize>,
 }
 
 impl FromStr for Instruction {
-    type Err = ();
+    type Err = miette::Report;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let [_, count, _, source, _, target] =
-            s.split_ascii_whitespace().array_chunks().next().unwrap();
-        Ok(Self {
-            source: source.parse::<usize>().unwrap(),
-            target: target.parse::<usize>().unwrap(),
-            count: count.parse::<usize>().unwrap(),
-        })
+        let invalid = || miette!("expected `move <count> from <stack> to <stack>`");
+        let number = |n: &str| n.parse::<usize>().map_err(|_| invalid());
+        match s.split_ascii_whitespace().collect::<Vec<_>>()[..] {
+            ["move", count, "from", source, "to", target] => Ok(Self {
+                source: number(source)?,
+                target: number(target)?,
+                count: number(count)?,
+                line: None,
+            }),
+            _ => Err(invalid()),
+        }
     }
 }
 
-#[derive(Debug)]
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.count, self.source, self.target
+        )
+    }
+}
+
+/// Whatever is between a crate's brackets in the drawing. Crates with the same
+/// label share one copy, so moving or cloning stacks never copies text.
+pub(crate) type Label = Rc<str>;
+
+/// Each stack is stored bottom crate first, so crates come and go at the end
+/// of its `Vec` and a move costs time in the crates moved, not the stack height.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct CrateStacks {
-    data: Vec<Vec<char>>,
+    data: Vec<Vec<Label>>,
 }
 
 impl CrateStacks {
@@ -35,155 +63,1535 @@ impl CrateStacks {
         Self { data }
     }
 
-    fn push(&mut self, stack: usize, char: char) {
-        self.data.get_mut(stack).unwrap().push(char);
+    fn push(&mut self, stack: usize, label: Label) {
+        self.data.get_mut(stack).unwrap().push(label);
+    }
+
+    /// Takes the top `count` crates off `stack` as one block, bottom crate first.
+    fn lift(&mut self, stack: usize, count: usize) -> Vec<Label> {
+        let stack = self.data.get_mut(stack).unwrap();
+        stack.split_off(stack.len() - count)
+    }
+
+    /// Sets a block of crates, bottom crate first, down on `stack`.
+    fn lower(&mut self, stack: usize, crates: Vec<Label>) {
+        self.data.get_mut(stack).unwrap().extend(crates);
+    }
+
+    fn apply_lift(&mut self, source: usize, target: usize, lift: Lift) {
+        let mut crates = self.lift(source, lift.count);
+        if lift.flip {
+            crates.reverse();
+        }
+        self.lower(target, crates);
+    }
+}
+
+/// One trip of the crane: `count` crates picked up together, set down in the
+/// same order unless `flip`ped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Lift {
+    count: usize,
+    flip: bool,
+}
+
+/// How a crane model carries out an instruction, as the lifts it makes.
+pub(crate) trait Crane {
+    fn name(&self) -> String;
+
+    /// The lifts for the `step`th (0-based) instruction, which moves `count` crates.
+    fn lifts(&self, step: usize, count: usize) -> Vec<Lift>;
+}
+
+/// Part one: one crate at a time.
+pub(crate) struct CrateMover9000;
+
+/// Part two: every crate in one block.
+pub(crate) struct CrateMover9001;
+
+/// Blocks like the 9001, but no more than `capacity` crates per lift.
+pub(crate) struct CappedCrane {
+    capacity: usize,
+}
+
+/// Blocks like the 9001, but flips the block on every other instruction.
+pub(crate) struct FlippingCrane;
+
+impl Crane for CrateMover9000 {
+    fn name(&self) -> String {
+        "CrateMover 9000".to_string()
+    }
+
+    fn lifts(&self, _step: usize, count: usize) -> Vec<Lift> {
+        vec![
+            Lift {
+                count: 1,
+                flip: false
+            };
+            count
+        ]
+    }
+}
+
+impl Crane for CrateMover9001 {
+    fn name(&self) -> String {
+        "CrateMover 9001".to_string()
+    }
+
+    fn lifts(&self, _step: usize, count: usize) -> Vec<Lift> {
+        vec![Lift { count, flip: false }]
+    }
+}
+
+impl Crane for CappedCrane {
+    fn name(&self) -> String {
+        format!("capped crane ({} per lift)", self.capacity)
+    }
+
+    fn lifts(&self, _step: usize, count: usize) -> Vec<Lift> {
+        let mut lifts = vec![
+            Lift {
+                count: self.capacity,
+                flip: false,
+            };
+            count / self.capacity
+        ];
+        let rest = count % self.capacity;
+        if rest > 0 {
+            lifts.push(Lift {
+                count: rest,
+                flip: false,
+            });
+        }
+        lifts
+    }
+}
+
+impl Crane for FlippingCrane {
+    fn name(&self) -> String {
+        "flipping crane".to_string()
     }
 
-    fn prepend(&mut self, stack: usize, char: char) {
-        self.data.get_mut(stack).unwrap().insert(0, char);
+    fn lifts(&self, step: usize, count: usize) -> Vec<Lift> {
+        vec![Lift {
+            count,
+            flip: step % 2 == 1,
+        }]
     }
+}
 
-    fn take(&mut self, stack: usize) -> char {
-        self.data.get_mut(stack).unwrap().remove(0)
+/// Picks a crane by its `--crane` name: `9000`, `9001`, `capped:<n>` or `flipping`.
+pub(crate) fn parse_crane(name: &str) -> Result<Box<dyn Crane>> {
+    Ok(match name.split_once(':') {
+        None if name == "9000" => Box::new(CrateMover9000),
+        None if name == "9001" => Box::new(CrateMover9001),
+        None if name == "flipping" => Box::new(FlippingCrane),
+        Some(("capped", capacity)) => {
+            let capacity = capacity.parse::<usize>().into_diagnostic()?;
+            if capacity == 0 {
+                return Err(miette!("crane capacity must be at least 1"));
+            }
+            Box::new(CappedCrane { capacity })
+        }
+        _ => return Err(miette!("unknown crane: {}", name)),
+    })
+}
+
+impl CrateStacks {
+    fn height(&self) -> usize {
+        self.data.iter().map(Vec::len).max().unwrap_or_default()
     }
 
-    fn swap(&mut self, source: usize, target: usize) {
-        let c = self.take(source);
-        self.prepend(target, c);
+    /// The crate `level` places up from the bottom of `stack`, if it's that tall.
+    fn get(&self, stack: usize, level: usize) -> Option<&str> {
+        self.data[stack].get(level).map(AsRef::as_ref)
     }
 
-    fn swap_n(&mut self, source: usize, target: usize, count: usize) {
-        let taken = self
+    /// The top crate of each stack, run together when every label is a single
+    /// character as in the puzzle, and space separated otherwise with `[]`
+    /// for an empty stack.
+    pub(crate) fn tops(&self) -> String {
+        let single = self
             .data
-            .get_mut(source)
+            .iter()
+            .flatten()
+            .all(|label| label.chars().count() == 1);
+        let (empty, separator) = if single { (" ", "") } else { ("[]", " ") };
+        self.data
+            .iter()
+            .map(|stack| stack.last().map_or(empty, AsRef::as_ref))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// How many columns each stack is drawn in: enough for its widest crate
+    /// and for the highest stack number.
+    fn cell_width(&self) -> usize {
+        self.data
+            .iter()
+            .flatten()
+            .map(|label| label.chars().count() + 2)
+            .chain([3, self.data.len().to_string().len()])
+            .max()
             .unwrap()
-            .splice(0..count, vec![])
-            .collect::<Vec<_>>();
-        self.data.get_mut(target).unwrap().splice(0..0, taken);
+    }
+
+    /// Draws the stacks like the puzzle does, with `flight` hanging above
+    /// stack `over` (bottom crate first), as a crane carrying them would.
+    pub(crate) fn render(&self, flight: &[Label], over: usize) -> String {
+        let width = self.cell_width().max(
+            flight
+                .iter()
+                .map(|label| label.chars().count() + 2)
+                .max()
+                .unwrap_or_default(),
+        );
+        let label = |label: &str| format!("{:^width$}", format!("[{}]", label));
+        let mut lines = vec![];
+
+        for (i, c) in flight.iter().rev().enumerate() {
+            let row = (0..self.data.len())
+                .map(|stack| {
+                    if stack == over {
+                        label(c)
+                    } else {
+                        " ".repeat(width)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push(if i == 0 {
+                format!("{}  <- crane", row.trim_end())
+            } else {
+                row
+            });
+        }
+
+        for level in (0..self.height()).rev() {
+            lines.push(
+                (0..self.data.len())
+                    .map(|stack| match self.get(stack, level) {
+                        Some(c) => label(c),
+                        None => " ".repeat(width),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+
+        lines.push(
+            (1..=self.data.len())
+                .map(|i| format!("{:^width$}", i))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        lines.join("\n")
     }
 }
 
 impl Display for CrateStacks {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for stack in self.data.iter() {
-            for char in stack {
-                write!(f, "{} ", char)?
-            }
-            write!(f, "\n")?
+        write!(f, "{}", self.render(&[], 0))
+    }
+}
+
+pub(crate) fn parse_file(file: &str) -> Result<(CrateStacks, Vec<Instruction>)> {
+    let lines = file.lines().collect::<Vec<_>>();
+    let blank = |line: &&str| line.trim().is_empty();
+    let start = lines
+        .iter()
+        .position(|line| !blank(line))
+        .unwrap_or(lines.len());
+    let end = lines[start..]
+        .iter()
+        .position(blank)
+        .map_or(lines.len(), |i| start + i);
+
+    let crate_stacks = parse_drawing(&lines[..end])?;
+
+    let instructions = lines
+        .iter()
+        .enumerate()
+        .skip(end)
+        .filter(|(_, line)| !blank(line))
+        .map(|(i, line)| {
+            let instruction = line
+                .parse::<Instruction>()
+                .map_err(|e| miette!("{} - line {}: {:?}", e, i + 1, line))?;
+            Ok(Instruction {
+                line: Some(i + 1),
+                ..instruction
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((crate_stacks, instructions))
+}
+
+/// Expands tabs to the next multiple of four columns, the width of a stack
+/// in the puzzle's drawings.
+fn expand_tabs(line: &str) -> Vec<char> {
+    let mut expanded = vec![];
+    for c in line.chars() {
+        if c == '\t' {
+            expanded.resize(expanded.len() / 4 * 4 + 4, ' ');
+        } else {
+            expanded.push(c);
         }
-        Ok(())
     }
+    expanded
 }
 
-pub(crate) fn parse_file(file: &str) -> (CrateStacks, Vec<Instruction>) {
-    let (head, tail) = file.split_once("\n\n").unwrap();
-    let head = head
-        .lines()
-        .take_while(|&line| !line.is_empty())
-        .collect::<Vec<_>>();
-    let instructions = tail
-        .lines()
-        .map(|s| s.parse::<Instruction>().unwrap())
+/// Reads the stacks from a drawing like the one the puzzle starts with, where
+/// `lines` are the first lines of the file. Crates belong to whichever stack
+/// number they sit over, so stacks can be any width and lines can be ragged.
+pub(crate) fn parse_drawing(lines: &[&str]) -> Result<CrateStacks> {
+    let lines = lines
+        .iter()
+        .map(|line| expand_tabs(line))
+        .enumerate()
+        .filter(|(_, line)| line.iter().any(|c| !c.is_whitespace()))
         .collect::<Vec<_>>();
+    let ((i, numbers), rows) = lines
+        .split_last()
+        .ok_or_else(|| miette!("expected a drawing of the stacks"))?;
 
-    let (&crate_indices, crate_data) = head.split_last().unwrap();
+    let mut columns = vec![];
+    let mut col = 0;
+    while col < numbers.len() {
+        if numbers[col].is_whitespace() {
+            col += 1;
+            continue;
+        }
+        let start = col;
+        while col < numbers.len() && !numbers[col].is_whitespace() {
+            col += 1;
+        }
+        let number = numbers[start..col].iter().collect::<String>();
+        if number.parse::<usize>().ok() != Some(columns.len() + 1) {
+            return Err(miette!(
+                "line {}: expected stack number {}, found {:?}",
+                i + 1,
+                columns.len() + 1,
+                number
+            ));
+        }
+        columns.push(start..col);
+    }
 
-    let num_crates = crate_indices
-        .split_whitespace()
-        .map(|x| x.parse::<usize>().unwrap())
-        .max()
-        .unwrap();
+    let mut crate_stacks = CrateStacks::new(columns.len());
+    let mut labels: HashSet<Label> = HashSet::new();
 
-    let mut crate_stacks = CrateStacks::new(num_crates);
+    for (height, (i, row)) in rows.iter().rev().enumerate() {
+        let mut col = 0;
+        while col < row.len() {
+            match row[col] {
+                c if c.is_whitespace() => {
+                    col += 1;
+                    continue;
+                }
+                '[' => {}
+                c => {
+                    return Err(miette!(
+                        "line {}: unexpected {:?} at column {}",
+                        i + 1,
+                        c,
+                        col + 1
+                    ))
+                }
+            }
 
-    for &line in crate_data {
-        let chunks = format!("{} ", line)
-            .chars()
-            .array_chunks()
-            .enumerate()
-            .map(|(col, [_, c, _, _])| (col, c))
-            .filter(|(_, c)| !c.is_whitespace())
-            .collect::<Vec<_>>();
+            let start = col;
+            let end = row[start..]
+                .iter()
+                .position(|&c| c == ']')
+                .map(|len| start + len)
+                .ok_or_else(|| miette!("line {}: unclosed crate at column {}", i + 1, start + 1))?;
+            let label = row[start + 1..end].iter().collect::<String>();
+            let label = match labels.get(label.as_str()) {
+                Some(label) => Rc::clone(label),
+                None => {
+                    let label = Label::from(label);
+                    labels.insert(Rc::clone(&label));
+                    label
+                }
+            };
+            if label.trim().is_empty() {
+                return Err(miette!(
+                    "line {}: crate with no label at column {}",
+                    i + 1,
+                    start + 1
+                ));
+            }
+            col = end + 1;
+
+            let stacks = columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| column.start <= end && start < column.end)
+                .map(|(stack, _)| stack)
+                .collect::<Vec<_>>();
+            let stack = match stacks[..] {
+                [stack] => stack,
+                [] => {
+                    return Err(miette!(
+                        "line {}: crate [{}] isn't over a stack number",
+                        i + 1,
+                        label
+                    ))
+                }
+                _ => {
+                    return Err(miette!(
+                        "line {}: crate [{}] is over more than one stack number",
+                        i + 1,
+                        label
+                    ))
+                }
+            };
+
+            let below = crate_stacks.data[stack].len();
+            if below != height {
+                return Err(miette!(
+                    "line {}: crate [{}] in stack {} {}",
+                    i + 1,
+                    label,
+                    stack + 1,
+                    if below < height {
+                        "has nothing under it"
+                    } else {
+                        "shares its place with another crate"
+                    }
+                ));
+            }
+            crate_stacks.push(stack, label);
+        }
+    }
+
+    Ok(crate_stacks)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum InstructionProblem {
+    NoSuchStack {
+        stack: usize,
+        stacks: usize,
+    },
+    NotEnoughCrates {
+        stack: usize,
+        requested: usize,
+        available: usize,
+    },
+}
+
+impl Display for InstructionProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchStack { stack, stacks } => {
+                write!(
+                    f,
+                    "there is no stack {} (stacks are 1 to {})",
+                    stack, stacks
+                )
+            }
+            Self::NotEnoughCrates {
+                stack,
+                requested,
+                available,
+            } => write!(
+                f,
+                "can't move {} crates from stack {}, it only holds {}",
+                requested, stack, available
+            ),
+        }
+    }
+}
+
+impl Instruction {
+    /// Whether this instruction can run against `crate_stacks` as they are now.
+    fn check(&self, crate_stacks: &CrateStacks) -> Option<InstructionProblem> {
+        let stacks = crate_stacks.data.len();
+        for stack in [self.source, self.target] {
+            if stack == 0 || stack > stacks {
+                return Some(InstructionProblem::NoSuchStack { stack, stacks });
+            }
+        }
+
+        let available = crate_stacks.data[self.source - 1].len();
+        if self.count > available {
+            return Some(InstructionProblem::NotEnoughCrates {
+                stack: self.source,
+                requested: self.count,
+                available,
+            });
+        }
+
+        None
+    }
+
+    fn validate(
+        &self,
+        step: usize,
+        crate_stacks: &CrateStacks,
+        input: &str,
+    ) -> Result<(), InvalidInstruction> {
+        match self.check(crate_stacks) {
+            None => Ok(()),
+            Some(problem) => Err(InvalidInstruction {
+                step: step + 1,
+                problem,
+                input: input.to_string(),
+                span: self.line.map(|line| line_span(input, line)),
+                stacks: format!("stacks before this step:\n{}", crate_stacks),
+            }),
+        }
+    }
+}
+
+/// The first instruction that can't run, located in the puzzle input, with
+/// the stacks as they were just before it.
+#[derive(Debug, Diagnostic)]
+#[diagnostic(code(day_05::invalid_instruction))]
+pub(crate) struct InvalidInstruction {
+    step: usize,
+    problem: InstructionProblem,
+    #[source_code]
+    input: String,
+    #[label("{problem}")]
+    span: Option<SourceSpan>,
+    #[help]
+    stacks: String,
+}
 
-        for (col, c) in chunks {
-            crate_stacks.push(col, c);
+impl Display for InvalidInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid instruction at step {}", self.step)?;
+        if let Some(line) = self.line() {
+            write!(f, " (line {})", line)?;
         }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+impl std::error::Error for InvalidInstruction {}
+
+impl InvalidInstruction {
+    /// The instruction's line in the puzzle input, unless it was planned
+    /// rather than read from one.
+    fn line(&self) -> Option<usize> {
+        self.span
+            .map(|span| self.input[..span.offset()].matches('\n').count() + 1)
     }
+}
 
-    (crate_stacks, instructions)
+fn line_span(input: &str, line: usize) -> SourceSpan {
+    let offset = input
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum::<usize>();
+    let len = input[offset..].lines().next().map_or(0, str::len);
+    (offset, len).into()
 }
 
+/// Runs `instructions` with `crane`, checking each one against the stacks
+/// before it runs and stopping at the first one that would fail.
 pub(crate) fn apply_instructions(
     mut crate_stacks: CrateStacks,
-    instructions: &Vec<Instruction>,
-) -> CrateStacks {
-    for &Instruction {
-        source,
-        target,
-        count,
-    } in instructions
-    {
-        for _ in 0..count {
-            crate_stacks.swap(source - 1, target - 1);
+    instructions: &[Instruction],
+    crane: &dyn Crane,
+    input: &str,
+) -> Result<CrateStacks, InvalidInstruction> {
+    for (step, instruction) in instructions.iter().enumerate() {
+        instruction.validate(step, &crate_stacks, input)?;
+        for lift in crane.lifts(step, instruction.count) {
+            crate_stacks.apply_lift(instruction.source - 1, instruction.target - 1, lift);
+        }
+    }
+
+    Ok(crate_stacks)
+}
+
+/// A crane program recorded as the lifts each step made, so the stacks can
+/// be wound back and forth to any step without keeping a copy of each state.
+pub(crate) struct Timeline {
+    stacks: CrateStacks,
+    steps: Vec<Vec<(usize, usize, Lift)>>,
+    position: usize,
+}
+
+/// One stack that differs between two steps, bottom crate first.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct StackChange {
+    stack: usize,
+    before: Vec<Label>,
+    after: Vec<Label>,
+}
+
+impl Display for StackChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |crates: &[Label]| {
+            if crates.is_empty() {
+                "(empty)".to_string()
+            } else {
+                crates
+                    .iter()
+                    .map(|label| format!("[{}]", label))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        };
+        write!(
+            f,
+            "stack {}: {} -> {}",
+            self.stack,
+            show(&self.before),
+            show(&self.after)
+        )
+    }
+}
+
+impl Timeline {
+    /// Runs `instructions` like `apply_instructions`, leaving the timeline at
+    /// the last step.
+    pub(crate) fn record(
+        mut crate_stacks: CrateStacks,
+        instructions: &[Instruction],
+        crane: &dyn Crane,
+        input: &str,
+    ) -> Result<Self, InvalidInstruction> {
+        let mut steps = Vec::with_capacity(instructions.len());
+        for (step, instruction) in instructions.iter().enumerate() {
+            instruction.validate(step, &crate_stacks, input)?;
+            let (source, target) = (instruction.source - 1, instruction.target - 1);
+            let lifts = crane.lifts(step, instruction.count);
+            for &lift in &lifts {
+                crate_stacks.apply_lift(source, target, lift);
+            }
+            steps.push(
+                lifts
+                    .into_iter()
+                    .map(|lift| (source, target, lift))
+                    .collect(),
+            );
+        }
+
+        Ok(Self {
+            stacks: crate_stacks,
+            position: steps.len(),
+            steps,
+        })
+    }
+
+    /// How many steps were recorded.
+    pub(crate) fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Takes back the last applied step, if any.
+    pub(crate) fn undo(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        // A lift puts its block back where it came from by lifting it again,
+        // flipping it back if it was flipped.
+        for &(source, target, lift) in self.steps[self.position].iter().rev() {
+            self.stacks.apply_lift(target, source, lift);
+        }
+        true
+    }
+
+    /// Applies the next step again after an `undo`, if any.
+    pub(crate) fn redo(&mut self) -> bool {
+        if self.position == self.steps.len() {
+            return false;
+        }
+        for &(source, target, lift) in &self.steps[self.position] {
+            self.stacks.apply_lift(source, target, lift);
+        }
+        self.position += 1;
+        true
+    }
+
+    pub(crate) fn seek(&mut self, step: usize) -> Result<&CrateStacks> {
+        if step > self.steps.len() {
+            return Err(miette!(
+                "step {} is past the end, there are {} steps",
+                step,
+                self.steps.len()
+            ));
+        }
+        while self.position > step {
+            self.undo();
+        }
+        while self.position < step {
+            self.redo();
+        }
+        Ok(&self.stacks)
+    }
+
+    /// The stacks after `step` steps, leaving the timeline there.
+    pub(crate) fn state_at(&mut self, step: usize) -> Result<CrateStacks> {
+        self.seek(step).cloned()
+    }
+
+    /// The stacks that differ between step `from` and step `to`.
+    pub(crate) fn diff(&mut self, from: usize, to: usize) -> Result<Vec<StackChange>> {
+        let before = self.state_at(from)?;
+        let after = self.seek(to)?;
+        Ok(before
+            .data
+            .iter()
+            .zip(&after.data)
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(i, (before, after))| StackChange {
+                stack: i + 1,
+                before: before.clone(),
+                after: after.clone(),
+            })
+            .collect())
+    }
+
+    /// The first step after which `label` is somewhere in `stack` (1-based),
+    /// counting the starting drawing as step 0.
+    pub(crate) fn first_arrival(&mut self, label: &str, stack: usize) -> Option<usize> {
+        let stack = stack.checked_sub(1)?;
+        self.stacks.data.get(stack)?;
+        self.seek(0).ok()?;
+        loop {
+            if self.stacks.data[stack].iter().any(|c| &**c == label) {
+                return Some(self.position);
+            }
+            if !self.redo() {
+                return None;
+            }
+        }
+    }
+}
+
+/// Answers `history` subcommand queries against a recorded timeline:
+/// `at <step>`, `diff <from> <to>` or `arrival <crate> <stack>`.
+pub(crate) fn query_history(timeline: &mut Timeline, query: &[String]) -> Result<String> {
+    let number = |i: usize| {
+        query
+            .get(i)
+            .ok_or_else(|| miette!("history {}: missing argument", query[0]))?
+            .parse::<usize>()
+            .into_diagnostic()
+    };
+
+    match query.first().map(String::as_str) {
+        Some("at") => {
+            let step = number(1)?;
+            let steps = timeline.len();
+            let stacks = timeline.seek(step)?;
+            Ok(format!("\nAfter step {}/{}:\n{}\n", step, steps, stacks))
+        }
+        Some("diff") => {
+            let (from, to) = (number(1)?, number(2)?);
+            let changes = timeline.diff(from, to)?;
+            let changes = if changes.is_empty() {
+                "no changes".to_string()
+            } else {
+                changes
+                    .iter()
+                    .map(StackChange::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            Ok(format!("\nStep {} -> step {}:\n{}\n", from, to, changes))
+        }
+        Some("arrival") => {
+            let label = query
+                .get(1)
+                .ok_or_else(|| miette!("history arrival: missing crate"))?;
+            let stack = number(2)?;
+            Ok(match timeline.first_arrival(label, stack) {
+                Some(step) => format!(
+                    "\nCrate {} first reaches stack {} at step {}\n",
+                    label, stack, step
+                ),
+                None => format!("\nCrate {} never reaches stack {}\n", label, stack),
+            })
+        }
+        _ => Err(miette!(
+            "usage: history at <step> | diff <from> <to> | arrival <crate> <stack>"
+        )),
+    }
+}
+
+/// Works out moves from one arrangement of crates to another, a crate at a
+/// time: each round picks the stack whose next missing crate is cheapest to
+/// reach, clears anything unwanted off it, digs the crate out and sets it down.
+struct Planner<'a> {
+    stacks: CrateStacks,
+    goal: &'a CrateStacks,
+    blocks: bool,
+    plan: Vec<Instruction>,
+}
+
+impl Planner<'_> {
+    /// How many crates at the bottom of `stack` are already where the goal has them.
+    fn settled(&self, stack: usize) -> usize {
+        self.stacks.data[stack]
+            .iter()
+            .zip(&self.goal.data[stack])
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    fn unsettled(&self, stack: usize) -> usize {
+        self.stacks.data[stack].len() - self.settled(stack)
+    }
+
+    /// The unsettled `label` with the fewest crates on top of it, outside `stack`,
+    /// as its stack, level and the number of crates above it.
+    fn nearest(&self, label: &str, stack: usize) -> Option<(usize, usize, usize)> {
+        (0..self.stacks.data.len())
+            .filter(|&other| other != stack)
+            .flat_map(|other| {
+                let crates = &self.stacks.data[other];
+                (self.settled(other)..crates.len())
+                    .filter(move |&level| &*crates[level] == label)
+                    .map(move |level| (other, level, crates.len() - 1 - level))
+            })
+            .min_by_key(|&(_, _, above)| above)
+    }
+
+    /// Somewhere to put crates that are in the way, away from `avoid`.
+    fn buffer(&self, avoid: &[usize]) -> Result<usize> {
+        (0..self.stacks.data.len())
+            .filter(|stack| !avoid.contains(stack))
+            .min_by_key(|&stack| self.unsettled(stack))
+            .ok_or_else(|| miette!("not enough stacks to dig crates out, need at least three"))
+    }
+
+    fn move_crates(&mut self, source: usize, target: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        // Moving one crate at a time turns the block over on the way.
+        self.stacks.apply_lift(
+            source,
+            target,
+            Lift {
+                count,
+                flip: !self.blocks,
+            },
+        );
+        match self.plan.last_mut() {
+            // Back-to-back single-crate moves between the same stacks are one
+            // instruction to a CrateMover 9000, but not to a 9001.
+            Some(last)
+                if !self.blocks && (last.source, last.target) == (source + 1, target + 1) =>
+            {
+                last.count += count
+            }
+            _ => self.plan.push(Instruction {
+                source: source + 1,
+                target: target + 1,
+                count,
+                line: None,
+            }),
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<Instruction>> {
+        loop {
+            let next = (0..self.stacks.data.len())
+                .filter(|&stack| self.settled(stack) < self.goal.data[stack].len())
+                .min_by_key(|&stack| {
+                    let label = &self.goal.data[stack][self.settled(stack)];
+                    let above = self.nearest(label, stack).map_or(0, |(_, _, above)| above);
+                    self.unsettled(stack) + above
+                });
+            let Some(stack) = next else {
+                return Ok(self.plan);
+            };
+
+            let level = self.settled(stack);
+            let goal = self.goal;
+            let wanted = &goal.data[stack][level..];
+            let label = &wanted[0];
+
+            let excess = self.unsettled(stack);
+            if excess > 0 {
+                let avoid = self.nearest(label, stack).map(|(source, _, _)| source);
+                let buffer = self
+                    .buffer(&[stack, avoid.unwrap_or(stack)])
+                    .or_else(|_| self.buffer(&[stack]))?;
+                self.move_crates(stack, buffer, excess);
+            }
+
+            let (source, from, _) = self
+                .nearest(label, stack)
+                .expect("an unsettled crate for every unfilled place");
+            let crates = &self.stacks.data[source];
+            let (dig, count) = if self.blocks {
+                // Lift the crate with as many of the ones above it as belong on top.
+                let count = crates[from..]
+                    .iter()
+                    .zip(wanted)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (crates.len() - from - count, count)
+            } else {
+                // Move the crate, then any below it that come next.
+                let count = crates[self.settled(source)..=from]
+                    .iter()
+                    .rev()
+                    .zip(wanted)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (crates.len() - 1 - from, count)
+            };
+
+            if dig > 0 {
+                let buffer = self.buffer(&[stack, source])?;
+                self.move_crates(source, buffer, dig);
+            }
+            self.move_crates(source, stack, count);
         }
     }
+}
+
+/// Plans instructions that turn `start` into `goal` on a CrateMover 9001 if
+/// `blocks` is set, or a CrateMover 9000 otherwise.
+pub(crate) fn plan_moves(
+    start: &CrateStacks,
+    goal: &CrateStacks,
+    blocks: bool,
+) -> Result<Vec<Instruction>> {
+    if start.data.len() != goal.data.len() {
+        return Err(miette!(
+            "the goal has {} stacks, but the start has {}",
+            goal.data.len(),
+            start.data.len()
+        ));
+    }
+    let sorted = |stacks: &CrateStacks| {
+        let mut crates = stacks.data.concat();
+        crates.sort_unstable();
+        crates
+    };
+    if sorted(start) != sorted(goal) {
+        return Err(miette!(
+            "the goal doesn't have the same crates as the start"
+        ));
+    }
 
-    crate_stacks
+    Planner {
+        stacks: start.clone(),
+        goal,
+        blocks,
+        plan: vec![],
+    }
+    .run()
 }
 
-pub(crate) fn apply_instructions_part_2(
+/// Replays `instructions` one frame at a time: the stacks before each move,
+/// the crates lifted above their source, then above their target.
+pub(crate) fn animate<W: Write>(
+    out: &mut W,
     mut crate_stacks: CrateStacks,
-    instructions: &Vec<Instruction>,
-) -> CrateStacks {
-    for &Instruction {
-        source,
-        target,
-        count,
-    } in instructions
-    {
-        crate_stacks.swap_n(source - 1, target - 1, count);
+    instructions: &[Instruction],
+    crane: &dyn Crane,
+    delay: Duration,
+) -> Result<CrateStacks> {
+    let mut frame = |crate_stacks: &CrateStacks, caption: &str, flight: &[Label], over: usize| {
+        write!(
+            out,
+            "\x1b[2J\x1b[H{}\n\n{}\n",
+            caption,
+            crate_stacks.render(flight, over)
+        )
+        .into_diagnostic()?;
+        out.flush().into_diagnostic()?;
+        thread::sleep(delay);
+        Ok::<_, miette::Report>(())
+    };
+
+    for (step, instruction) in instructions.iter().enumerate() {
+        let caption = format!(
+            "{} step {}/{}: move {} from {} to {}",
+            crane.name(),
+            step + 1,
+            instructions.len(),
+            instruction.count,
+            instruction.source,
+            instruction.target
+        );
+        let (source, target) = (instruction.source - 1, instruction.target - 1);
+
+        for lift in crane.lifts(step, instruction.count) {
+            frame(&crate_stacks, &caption, &[], 0)?;
+            let mut flight = crate_stacks.lift(source, lift.count);
+            frame(&crate_stacks, &caption, &flight, source)?;
+            if lift.flip {
+                flight.reverse();
+            }
+            frame(&crate_stacks, &caption, &flight, target)?;
+            crate_stacks.lower(target, flight);
+        }
     }
 
-    crate_stacks
+    frame(&crate_stacks, "done", &[], 0)?;
+
+    Ok(crate_stacks)
 }
 
-pub(crate) fn solve() -> String {
-    let input = env::args()
-        .nth(1)
-        .map(fs::read_to_string)
-        .expect("failed to read input file");
+pub(crate) fn solve(input: String, options: &[String]) -> Result<String> {
+    let crane = get_option(options, "--crane")
+        .map(parse_crane)
+        .transpose()?;
+
+    if let Some(i) = options.iter().position(|option| option == "history") {
+        let (crate_stacks, instructions) = parse_file(&input)?;
+        let crane = crane.as_deref().unwrap_or(&CrateMover9000);
+        let mut timeline = Timeline::record(crate_stacks, &instructions, crane, &input)?;
+        return query_history(&mut timeline, &options[i + 1..]);
+    }
+
+    if let Some(path) = get_option(options, "--plan") {
+        let blocks = match get_option(options, "--crane") {
+            None | Some("9000") => false,
+            Some("9001") => true,
+            Some(name) => return Err(miette!("can't plan moves for crane: {}", name)),
+        };
+        let goal = fs::read_to_string(path).into_diagnostic()?;
+        let goal = parse_drawing(&goal.lines().collect::<Vec<_>>())?;
+        let (crate_stacks, _) = parse_file(&input)?;
+        let plan = plan_moves(&crate_stacks, &goal, blocks)?
+            .iter()
+            .map(Instruction::to_string)
+            .collect::<Vec<_>>();
 
-    let (crate_stacks, instructions) = input
-        .as_deref()
-        .map(parse_file)
-        .expect("failed to parse data");
+        // The start drawing and the moves together make a puzzle input.
+        return Ok(format!("{}\n\n{}", crate_stacks, plan.join("\n")));
+    }
 
-    let finished_stacks_part_1 = apply_instructions(crate_stacks, &instructions);
+    if has_flag(options, "--animate") {
+        let delay = get_option(options, "--delay")
+            .map(str::parse::<u64>)
+            .transpose()
+            .into_diagnostic()?
+            .unwrap_or(250);
 
-    let (crate_stacks, instructions) = input
-        .as_deref()
-        .map(parse_file)
-        .expect("failed to parse data");
+        let crane = crane.as_deref().unwrap_or(&CrateMover9000);
+        let (crate_stacks, instructions) = parse_file(&input)?;
+        apply_instructions(crate_stacks, &instructions, crane, &input)?;
 
-    let finished_stacks_part_2 = apply_instructions_part_2(crate_stacks, &instructions);
+        let (crate_stacks, instructions) = parse_file(&input)?;
+        let finished = animate(
+            &mut std::io::stdout(),
+            crate_stacks,
+            &instructions,
+            crane,
+            Duration::from_millis(delay),
+        )?;
 
-    let top_crates_part_1 = finished_stacks_part_1
-        .data
-        .into_iter()
-        .map(|stack| stack.get(0).unwrap_or(&' ').clone())
-        .collect::<String>();
+        return Ok(format!("\nTop crates: {}\n", finished.tops()));
+    }
 
-    let top_crates_part_2 = finished_stacks_part_2
-        .data
-        .into_iter()
-        .map(|stack| stack.get(0).unwrap_or(&' ').clone())
-        .collect::<String>();
+    if let Some(crane) = crane {
+        let (crate_stacks, instructions) = parse_file(&input)?;
+        let finished = apply_instructions(crate_stacks, &instructions, crane.as_ref(), &input)?;
+        return Ok(format!(
+            "\n{}: top crates: {}\n",
+            crane.name(),
+            finished.tops()
+        ));
+    }
 
-    format!(
+    let (crate_stacks, instructions) = parse_file(&input)?;
+    let finished_stacks_part_1 =
+        apply_instructions(crate_stacks, &instructions, &CrateMover9000, &input)?;
+
+    let (crate_stacks, instructions) = parse_file(&input)?;
+    let finished_stacks_part_2 =
+        apply_instructions(crate_stacks, &instructions, &CrateMover9001, &input)?;
+
+    Ok(format!(
         r#"
 Part 1: top crates: {}
 Part 2: top crates: {}
 "#,
-        top_crates_part_1, top_crates_part_2,
-    )
+        finished_stacks_part_1.tops(),
+        finished_stacks_part_2.tops(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    extern crate test;
+
+    use super::*;
+    use crate::rng::XorShift;
+    use rstest::*;
+    use test::Bencher;
+
+    static INPUT: &str = "    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2";
+
+    #[rstest(input, expected, case(INPUT, "CMZ"))]
+    fn test_part_1(input: &str, expected: &str) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let finished =
+            apply_instructions(crate_stacks, &instructions, &CrateMover9000, input).unwrap();
+        assert_eq!(finished.tops(), expected);
+    }
+
+    #[rstest(input, expected, case(INPUT, "MCD"))]
+    fn test_part_2(input: &str, expected: &str) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let finished =
+            apply_instructions(crate_stacks, &instructions, &CrateMover9001, input).unwrap();
+        assert_eq!(finished.tops(), expected);
+    }
+
+    #[rstest(
+        input,
+        crane,
+        expected,
+        case(INPUT, "9000", "CMZ"),
+        case(INPUT, "9001", "MCD"),
+        case(INPUT, "capped:1", "CMZ"),
+        case(INPUT, "capped:2", "MCZ"),
+        case(INPUT, "capped:3", "MCD"),
+        case(INPUT, "flipping", "MCZ")
+    )]
+    fn test_cranes(input: &str, crane: &str, expected: &str) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let crane = parse_crane(crane).unwrap();
+        let finished =
+            apply_instructions(crate_stacks, &instructions, crane.as_ref(), input).unwrap();
+        assert_eq!(finished.tops(), expected);
+    }
+
+    #[rstest(crane, case("9002"), case("capped:0"), case("capped:x"))]
+    fn test_unknown_crane(crane: &str) {
+        assert!(parse_crane(crane).is_err());
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(
+            "move 1 from 2 to 1\nmove 4 from 1 to 3",
+            "invalid instruction at step 2 (line 7): can't move 4 crates from stack 1, it only holds 3"
+        ),
+        case(
+            "move 1 from 2 to 4",
+            "invalid instruction at step 1 (line 6): there is no stack 4 (stacks are 1 to 3)"
+        ),
+        case(
+            "move 1 from 0 to 1",
+            "invalid instruction at step 1 (line 6): there is no stack 0 (stacks are 1 to 3)"
+        )
+    )]
+    fn test_invalid_instructions(input: &str, expected: &str) {
+        let (drawing, _) = INPUT.split_once("\n\n").unwrap();
+        let input = format!("{}\n\n{}", drawing, input);
+        let (crate_stacks, instructions) = parse_file(&input).unwrap();
+        let error =
+            apply_instructions(crate_stacks, &instructions, &CrateMover9000, &input).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        let span = error.span.unwrap();
+        assert_eq!(
+            &input[span.offset()..span.offset() + span.len()],
+            input.lines().nth(error.line().unwrap() - 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_unparsed_instruction() {
+        let (crate_stacks, _) = parse_file(INPUT).unwrap();
+        let instruction = "move 5 from 1 to 2".parse::<Instruction>().unwrap();
+        let error =
+            apply_instructions(crate_stacks, &[instruction], &CrateMover9000, INPUT).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid instruction at step 1: can't move 5 crates from stack 1, it only holds 2"
+        );
+        assert_eq!(error.span, None);
+    }
+
+    #[rstest(input, case(INPUT))]
+    fn test_timeline(input: &str) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let mut timeline =
+            Timeline::record(crate_stacks, &instructions, &CrateMover9000, input).unwrap();
+        assert_eq!(
+            (timeline.position, timeline.stacks.tops()),
+            (4, "CMZ".to_string())
+        );
+
+        assert!(timeline.undo());
+        assert_eq!(timeline.stacks.tops(), "M Z");
+        assert!(timeline.redo());
+        assert!(!timeline.redo());
+        assert_eq!(timeline.stacks.tops(), "CMZ");
+
+        let (drawing, _) = input.split_once("\n\n").unwrap();
+        assert_eq!(timeline.state_at(0).unwrap().to_string(), drawing);
+        assert!(!timeline.undo());
+        assert!(timeline.seek(5).is_err());
+    }
+
+    #[rstest(
+        input,
+        crane,
+        from,
+        to,
+        expected,
+        case(
+            INPUT,
+            "9000",
+            1,
+            2,
+            vec!["stack 1: [Z] [N] [D] -> (empty)", "stack 3: [P] -> [P] [D] [N] [Z]"]
+        ),
+        case(
+            INPUT,
+            "9001",
+            1,
+            2,
+            vec!["stack 1: [Z] [N] [D] -> (empty)", "stack 3: [P] -> [P] [Z] [N] [D]"]
+        ),
+        case(
+            INPUT,
+            "9000",
+            2,
+            0,
+            vec![
+                "stack 1: (empty) -> [Z] [N]",
+                "stack 2: [M] [C] -> [M] [C] [D]",
+                "stack 3: [P] [D] [N] [Z] -> [P]"
+            ]
+        ),
+        case(INPUT, "9000", 3, 3, vec![])
+    )]
+    fn test_timeline_diff(
+        input: &str,
+        crane: &str,
+        from: usize,
+        to: usize,
+        mut expected: Vec<&str>,
+    ) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let crane = parse_crane(crane).unwrap();
+        let mut timeline =
+            Timeline::record(crate_stacks, &instructions, crane.as_ref(), input).unwrap();
+        let mut changes = timeline
+            .diff(from, to)
+            .unwrap()
+            .iter()
+            .map(StackChange::to_string)
+            .collect::<Vec<_>>();
+        changes.sort();
+        expected.sort();
+        assert_eq!(changes, expected);
+    }
+
+    #[rstest(
+        input,
+        label,
+        stack,
+        expected,
+        case(INPUT, "D", 3, Some(2)),
+        case(INPUT, "Z", 3, Some(2)),
+        case(INPUT, "M", 2, Some(0)),
+        case(INPUT, "C", 1, Some(3)),
+        case(INPUT, "P", 1, None),
+        case(INPUT, "P", 4, None)
+    )]
+    fn test_first_arrival(input: &str, label: &str, stack: usize, expected: Option<usize>) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let mut timeline =
+            Timeline::record(crate_stacks, &instructions, &CrateMover9000, input).unwrap();
+        assert_eq!(timeline.first_arrival(label, stack), expected);
+    }
+
+    #[rstest(
+        input,
+        crane,
+        case(INPUT.to_string(), "9000"),
+        case(INPUT.to_string(), "9001"),
+        case(generate_input(9, 10, 200, 6), "9000"),
+        case(generate_input(9, 10, 200, 6), "9001"),
+        case(generate_input(3, 8, 50, 5), "9001")
+    )]
+    fn test_plan_moves(input: String, crane: &str) {
+        let (start, instructions) = parse_file(&input).unwrap();
+        let (goal, _) = parse_file(&input).unwrap();
+        let goal = apply_instructions(goal, &instructions, &CrateMover9000, &input).unwrap();
+
+        let plan = plan_moves(&start, &goal, crane == "9001").unwrap();
+        let replay = format!(
+            "{}\n\n{}",
+            start,
+            plan.iter()
+                .map(Instruction::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        let (start, plan) = parse_file(&replay).unwrap();
+        let crane = parse_crane(crane).unwrap();
+        let finished = apply_instructions(start, &plan, crane.as_ref(), &replay).unwrap();
+        assert_eq!(finished, goal);
+    }
+
+    #[rstest(
+        start,
+        goal,
+        case(&["[B]    ", "[A]    ", " 1   2 "], &["[A]    ", "[B]    ", " 1   2 "]),
+        case(&["[A] [B]", " 1   2 "], &["[A] [C]", " 1   2 "]),
+        case(&["[A] [B]", " 1   2 "], &["[A] [B]    ", " 1   2   3 "])
+    )]
+    fn test_plan_impossible(start: &[&str], goal: &[&str]) {
+        assert!(plan_moves(
+            &parse_drawing(start).unwrap(),
+            &parse_drawing(goal).unwrap(),
+            false
+        )
+        .is_err());
+    }
+
+    static WIDE: &str = "     [AB]                                                  
+     [CD] [E]                                              
+[F]  [GH] [I]                                          [J] 
+ 1    2    3    4    5    6    7    8    9    10   11   12 ";
+
+    #[rstest(input, case(INPUT), case(WIDE))]
+    fn test_render(input: &str) {
+        let (crate_stacks, _) = parse_file(input).unwrap();
+        let (drawing, _) = input.split_once("\n\n").unwrap_or((input, ""));
+        assert_eq!(crate_stacks.to_string(), drawing);
+    }
+
+    #[test]
+    fn test_wide_tops() {
+        let (crate_stacks, _) = parse_file(WIDE).unwrap();
+        assert_eq!(crate_stacks.tops(), "F AB E [] [] [] [] [] [] [] [] J");
+
+        let shared = parse_drawing(&["[AB] [AB]", " 1    2 "]).unwrap();
+        assert!(Rc::ptr_eq(&shared.data[0][0], &shared.data[1][0]));
+    }
+
+    #[rstest(stacks, case(3), case(12), case(100))]
+    fn test_render_round_trip(stacks: usize) {
+        let input = generate_input(stacks, 6, 0, 1);
+        let (crate_stacks, _) = parse_file(&input).unwrap();
+        let (rendered, _) = parse_file(&crate_stacks.to_string()).unwrap();
+        assert_eq!(rendered, crate_stacks);
+    }
+
+    #[rstest(
+        input,
+        case("    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3\n\nmove 1 from 2 to 1"),
+        case("\t[D]\n[N]\t[C]\n[Z] [M] [P]\n 1\t 2\t 3\n \t\nmove 1 from 2 to 1\n"),
+        case("\n    [D]    \r\n[N] [C]    \r\n[Z] [M] [P]\r\n 1   2   3 \r\n\r\nmove 1 from 2 to 1\r\n")
+    )]
+    fn test_ragged_drawings(input: &str) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let (expected, _) = parse_file(INPUT).unwrap();
+        assert_eq!((crate_stacks, instructions.len()), (expected, 1));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("[A]\n 1  3", "line 2: expected stack number 2, found \"3\""),
+        case("[A]", "line 1: expected stack number 1, found \"[A]\""),
+        case("[A] x\n 1   2", "line 1: unexpected 'x' at column 5"),
+        case("[A\n 1", "line 1: unclosed crate at column 1"),
+        case("[ ]\n 1", "line 1: crate with no label at column 1"),
+        case("        [A]\n 1   2", "line 1: crate [A] isn't over a stack number"),
+        case(
+            "[ABCDEFG]\n 1   2",
+            "line 1: crate [ABCDEFG] is over more than one stack number"
+        ),
+        case(
+            "    [A]\n[B]    \n 1   2",
+            "line 1: crate [A] in stack 2 has nothing under it"
+        ),
+        case(
+            "                                   [A][B]\n 1   2   3   4   5   6   7   8   9   10",
+            "line 1: crate [B] in stack 10 shares its place with another crate"
+        ),
+        case(
+            "[A] [B]\n 1   2\n\nmove 1 from 1 to 2\nmove one from 2 to 1",
+            "expected `move <count> from <stack> to <stack>` - line 5: \"move one from 2 to 1\""
+        )
+    )]
+    fn test_parse_errors(input: &str, expected: &str) {
+        let error = parse_file(input).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[rstest(
+        input,
+        crane,
+        expected,
+        case(INPUT, "9000", ("CMZ", 3 * (1 + 3 + 2 + 1) + 1)),
+        case(INPUT, "9001", ("MCD", 3 * 4 + 1))
+    )]
+    fn test_animate(input: &str, crane: &str, expected: (&str, usize)) {
+        let (crate_stacks, instructions) = parse_file(input).unwrap();
+        let mut out = vec![];
+        let finished = animate(
+            &mut out,
+            crate_stacks,
+            &instructions,
+            parse_crane(crane).unwrap().as_ref(),
+            Duration::ZERO,
+        )
+        .unwrap();
+        let frames = String::from_utf8(out).unwrap().matches("\x1b[2J").count();
+        assert_eq!((finished.tops().as_str(), frames), expected);
+    }
+
+    /// The old top-first storage, kept to benchmark against.
+    struct TopFirstStacks(Vec<Vec<Label>>);
+
+    impl TopFirstStacks {
+        fn swap(&mut self, source: usize, target: usize) {
+            let c = self.0[source].remove(0);
+            self.0[target].insert(0, c);
+        }
+
+        fn swap_n(&mut self, source: usize, target: usize, count: usize) {
+            let taken = self.0[source].splice(0..count, vec![]).collect::<Vec<_>>();
+            self.0[target].splice(0..0, taken);
+        }
+    }
+
+    /// A deterministic puzzle input with `stacks` stacks of `height` crates and
+    /// `moves` instructions, each moving up to `max_count` crates that exist.
+    fn generate_input(stacks: usize, height: usize, moves: usize, max_count: usize) -> String {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        let mut next = move |bound: usize| rng.index(bound);
+
+        let mut lines = (0..height)
+            .map(|_| {
+                (0..stacks)
+                    .map(|_| format!("[{}]", (b'A' + next(26) as u8) as char))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        lines.push(
+            (1..=stacks)
+                .map(|i| format!("{:^3}", i))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        lines.push(String::new());
+
+        let mut heights = vec![height; stacks];
+        for _ in 0..moves {
+            let source = (0..stacks)
+                .cycle()
+                .skip(next(stacks))
+                .find(|&stack| heights[stack] > 0)
+                .unwrap();
+            let target = (source + 1 + next(stacks - 1)) % stacks;
+            let count = 1 + next(heights[source].min(max_count));
+            heights[source] -= count;
+            heights[target] += count;
+            lines.push(format!(
+                "move {} from {} to {}",
+                count,
+                source + 1,
+                target + 1
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_matches_top_first() {
+        let input = generate_input(9, 50, 1_000, 40);
+        let (crate_stacks, instructions) = parse_file(&input).unwrap();
+
+        let mut part_1 = TopFirstStacks(
+            crate_stacks
+                .data
+                .iter()
+                .map(|stack| stack.iter().rev().cloned().collect())
+                .collect(),
+        );
+        let mut part_2 = TopFirstStacks(part_1.0.clone());
+        for instruction in &instructions {
+            for _ in 0..instruction.count {
+                part_1.swap(instruction.source - 1, instruction.target - 1);
+            }
+            part_2.swap_n(
+                instruction.source - 1,
+                instruction.target - 1,
+                instruction.count,
+            );
+        }
+        let tops = |stacks: TopFirstStacks| {
+            stacks
+                .0
+                .iter()
+                .map(|stack| stack.first().map_or(" ", AsRef::as_ref))
+                .collect::<String>()
+        };
+
+        let (crate_stacks, _) = parse_file(&input).unwrap();
+        let finished =
+            apply_instructions(crate_stacks, &instructions, &CrateMover9000, &input).unwrap();
+        assert_eq!(finished.tops(), tops(part_1));
+
+        let (crate_stacks, _) = parse_file(&input).unwrap();
+        let finished =
+            apply_instructions(crate_stacks, &instructions, &CrateMover9001, &input).unwrap();
+        assert_eq!(finished.tops(), tops(part_2));
+    }
+
+    #[bench]
+    fn bench_top_first(b: &mut Bencher) {
+        let input = generate_input(9, 5_000, 20_000, 500);
+        let (crate_stacks, instructions) = parse_file(&input).unwrap();
+        let stacks = crate_stacks
+            .data
+            .iter()
+            .map(|stack| stack.iter().rev().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            let mut stacks = TopFirstStacks(stacks.clone());
+            for instruction in &instructions {
+                stacks.swap_n(
+                    instruction.source - 1,
+                    instruction.target - 1,
+                    instruction.count,
+                );
+            }
+            stacks
+        });
+    }
+
+    #[bench]
+    fn bench_bottom_first(b: &mut Bencher) {
+        let input = generate_input(9, 5_000, 20_000, 500);
+        let (crate_stacks, instructions) = parse_file(&input).unwrap();
+        b.iter(|| {
+            let crate_stacks = CrateStacks {
+                data: crate_stacks.data.clone(),
+            };
+            apply_instructions(crate_stacks, &instructions, &CrateMover9001, &input).unwrap()
+        });
+    }
 }
@@ -1,43 +1,384 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashMap, fmt::Display, io::Write, ops::Range, str::FromStr, time::Duration,
+};
 
-fn get_start_packet_index(input: &str, window_size: usize) -> usize {
-    input
-        .chars()
-        .enumerate()
-        .collect::<Vec<_>>()
-        .windows(window_size)
-        .find(|slice| window_size == slice.iter().map(|(_, v)| v).collect::<HashSet<_>>().len())
-        .unwrap()
-        .last()
-        .unwrap()
-        .0
+use miette::{miette, IntoDiagnostic, Result};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt},
+    net::TcpStream,
+    time,
+};
+
+use crate::cli::{get_option, has_flag};
+
+/// Watches a datastream one byte at a time for `window` bytes in a row that
+/// are all different. Rather than re-checking every window, it keeps where
+/// each byte value was last seen and where the current run of distinct bytes
+/// starts, so each byte costs O(1) and memory doesn't grow with the input or
+/// the window.
+pub(crate) struct MarkerDetector {
+    window: usize,
+    last_seen: [Option<usize>; 256],
+    run_start: usize,
+    position: usize,
+    longest: (usize, usize),
+}
+
+impl MarkerDetector {
+    pub(crate) fn new(window: usize) -> Self {
+        Self {
+            window,
+            last_seen: [None; 256],
+            run_start: 0,
+            position: 0,
+            longest: (0, 0),
+        }
+    }
+
+    /// Feeds in the next byte, returning whether it completes a marker.
+    pub(crate) fn push(&mut self, byte: u8) -> bool {
+        if let Some(seen) = self.last_seen[byte as usize] {
+            self.run_start = self.run_start.max(seen + 1);
+        }
+        self.last_seen[byte as usize] = Some(self.position);
+        self.position += 1;
+
+        let len = self.position - self.run_start;
+        if len > self.longest.1 {
+            self.longest = (self.run_start, len);
+        }
+        len >= self.window
+    }
+
+    /// Starts looking for a new marker from the next byte on, so markers
+    /// don't share bytes.
+    pub(crate) fn restart(&mut self) {
+        self.run_start = self.position;
+    }
+
+    /// Where the first of the longest runs of distinct bytes so far starts,
+    /// and how long it is.
+    pub(crate) fn longest_run(&self) -> (usize, usize) {
+        self.longest
+    }
+}
+
+/// A datastream with no marker, and the closest it came to one.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NoMarker {
+    window: usize,
+    start: usize,
+    len: usize,
+}
+
+impl Display for NoMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.len == 0 {
+            return write!(f, "the datastream is empty");
+        }
+        write!(
+            f,
+            "needs {} distinct characters, the longest run is {}, characters {} to {}",
+            self.window,
+            self.len,
+            self.start + 1,
+            self.start + self.len
+        )
+    }
+}
+
+impl std::error::Error for NoMarker {}
+
+/// The index of the last byte of the first marker in `bytes`.
+pub(crate) fn find_marker<I: IntoIterator<Item = u8>>(
+    bytes: I,
+    window: usize,
+) -> Result<usize, NoMarker> {
+    let mut detector = MarkerDetector::new(window);
+    bytes
+        .into_iter()
+        .position(|byte| detector.push(byte))
+        .ok_or_else(|| {
+            let (start, len) = detector.longest_run();
+            NoMarker { window, start, len }
+        })
+}
+
+fn get_start_packet_index(input: &str, window_size: usize) -> Result<usize, NoMarker> {
+    find_marker(input.bytes(), window_size)
+}
+
+fn get_part_1_answer(input: &str) -> Result<usize, NoMarker> {
+    get_start_packet_index(input, 4).map(|i| i + 1)
+}
+
+fn get_part_2_answer(input: &str) -> Result<usize, NoMarker> {
+    get_start_packet_index(input, 14).map(|i| i + 1)
+}
+
+/// A kind of marker to look for: `window` distinct bytes in a row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MarkerDefinition {
+    name: String,
+    window: usize,
+}
+
+impl FromStr for MarkerDefinition {
+    type Err = miette::Report;
+
+    /// Reads `<name>:<window>`, e.g. `packet:4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, window) = s
+            .split_once(':')
+            .ok_or_else(|| miette!("expected `<name>:<window>`: {:?}", s))?;
+        let window = window
+            .parse::<usize>()
+            .ok()
+            .filter(|&window| window > 0)
+            .ok_or_else(|| miette!("expected a window of at least 1: {:?}", s))?;
+        Ok(Self {
+            name: name.to_string(),
+            window,
+        })
+    }
 }
 
-fn get_part_1_answer(input: &str) -> usize {
-    get_start_packet_index(input, 4) + 1
+/// The puzzle's two markers.
+pub(crate) fn default_markers() -> Vec<MarkerDefinition> {
+    vec![
+        MarkerDefinition {
+            name: "packet".to_string(),
+            window: 4,
+        },
+        MarkerDefinition {
+            name: "message".to_string(),
+            window: 14,
+        },
+    ]
 }
 
-fn get_part_2_answer(input: &str) -> usize {
-    get_start_packet_index(input, 14) + 1
+/// Where one marker of a given kind sits in the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Marker {
+    name: String,
+    range: Range<usize>,
 }
 
-pub(crate) fn solve(input: String) -> String {
-    let part_1_answer = get_part_1_answer(&input);
-    let part_2_answer = get_part_2_answer(&input);
+impl Display for Marker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} marker {}..{}",
+            self.name, self.range.start, self.range.end
+        )
+    }
+}
+
+/// Looks for several kinds of marker at once, as the stream arrives in
+/// chunks of any size. A marker never reuses bytes of the one before it.
+pub(crate) struct MarkerScanner<'a> {
+    markers: &'a [MarkerDefinition],
+    detectors: Vec<MarkerDetector>,
+    position: usize,
+}
+
+impl<'a> MarkerScanner<'a> {
+    pub(crate) fn new(markers: &'a [MarkerDefinition]) -> Self {
+        Self {
+            markers,
+            detectors: markers
+                .iter()
+                .map(|marker| MarkerDetector::new(marker.window))
+                .collect(),
+            position: 0,
+        }
+    }
+
+    /// Feeds in the next chunk of the stream, returning the markers it completes.
+    pub(crate) fn feed(&mut self, bytes: &[u8]) -> Vec<Marker> {
+        let mut found = vec![];
+        for &byte in bytes {
+            self.position += 1;
+            for (marker, detector) in self.markers.iter().zip(&mut self.detectors) {
+                if detector.push(byte) {
+                    found.push(Marker {
+                        name: marker.name.clone(),
+                        range: self.position - marker.window..self.position,
+                    });
+                    detector.restart();
+                }
+            }
+        }
+        found
+    }
+}
+
+/// One marker in the stream and the payload that follows it, up to the next
+/// marker of the same kind or the end of the stream.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Frame {
+    marker: Marker,
+    payload: Range<usize>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, payload {}..{} ({} bytes)",
+            self.marker,
+            self.payload.start,
+            self.payload.end,
+            self.payload.len()
+        )
+    }
+}
+
+/// Finds every marker of every kind in `bytes` and frames the stream between
+/// markers of the same kind. Frames are ordered by where their marker starts.
+pub(crate) fn decode(bytes: &[u8], markers: &[MarkerDefinition]) -> Vec<Frame> {
+    let found = MarkerScanner::new(markers).feed(bytes);
+
+    // Walking backwards, each marker's payload ends where the last one seen of
+    // the same kind starts.
+    let mut next_start: HashMap<&str, usize> = HashMap::new();
+    let mut frames = found
+        .iter()
+        .rev()
+        .map(|marker| {
+            let next = next_start
+                .insert(&marker.name, marker.range.start)
+                .unwrap_or(bytes.len());
+            Frame {
+                marker: marker.clone(),
+                payload: marker.range.end..next,
+            }
+        })
+        .collect::<Vec<_>>();
+    frames.reverse();
+    frames.sort_by_key(|frame| frame.marker.range.start);
+    frames
+}
 
-    format!(
+/// Reports markers from `reader` through `report` as soon as they arrive, and
+/// returns how many were found. At the end of the data it stops, or with
+/// `poll` set it waits that long and reads again, like `tail -f`. It also
+/// stops at the first error from `report`.
+pub(crate) async fn tail<R: AsyncRead + Unpin>(
+    mut reader: R,
+    markers: &[MarkerDefinition],
+    poll: Option<Duration>,
+    mut report: impl FnMut(Marker) -> Result<()>,
+) -> Result<usize> {
+    let mut scanner = MarkerScanner::new(markers);
+    let mut found = 0;
+    let mut buffer = [0; 4096];
+
+    loop {
+        let read = reader.read(&mut buffer).await.into_diagnostic()?;
+        if read == 0 {
+            match poll {
+                Some(poll) => {
+                    time::sleep(poll).await;
+                    continue;
+                }
+                None => return Ok(found),
+            }
+        }
+
+        for marker in scanner.feed(&buffer[..read]) {
+            found += 1;
+            report(marker)?;
+        }
+    }
+}
+
+fn parse_markers(options: &[String]) -> Result<Vec<MarkerDefinition>> {
+    match get_option(options, "--markers") {
+        Some(markers) => markers.split(',').map(str::parse).collect(),
+        None => Ok(default_markers()),
+    }
+}
+
+/// Follows `source` as it grows with `--tail`, or reads a local TCP socket at
+/// `source` with `--connect`, printing markers as they appear.
+pub(crate) async fn live(source: &str, options: &[String]) -> Result<String> {
+    let markers = parse_markers(options)?;
+    let mut stdout = std::io::stdout();
+    let report = |marker: Marker| {
+        writeln!(stdout, "{}", marker)
+            .and_then(|_| stdout.flush())
+            .into_diagnostic()
+    };
+
+    let found = if has_flag(options, "--connect") {
+        let stream = TcpStream::connect(source).await.into_diagnostic()?;
+        tail(stream, &markers, None, report).await?
+    } else {
+        let poll = get_option(options, "--poll")
+            .map(str::parse::<u64>)
+            .transpose()
+            .into_diagnostic()?
+            .unwrap_or(100);
+        let file = File::open(source).await.into_diagnostic()?;
+        tail(file, &markers, Some(Duration::from_millis(poll)), report).await?
+    };
+
+    Ok(format!("\nMarkers found: {}\n", found))
+}
+
+fn format_frames(frames: &[Frame]) -> String {
+    let frames = frames
+        .iter()
+        .map(Frame::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("\n{}\n", frames)
+}
+
+fn format_answer(answer: Result<usize, NoMarker>, marker: &str) -> String {
+    match answer {
+        Ok(index) => index.to_string(),
+        Err(e) => format!("no {} marker ({})", marker, e),
+    }
+}
+
+pub(crate) fn solve(input: String, options: &[String]) -> Result<String> {
+    if has_flag(options, "--decode") {
+        let markers = parse_markers(options)?;
+        return Ok(format_frames(&decode(
+            input.trim_end().as_bytes(),
+            &markers,
+        )));
+    }
+
+    let part_1_answer = format_answer(get_part_1_answer(&input), "start-of-packet");
+    let part_2_answer = format_answer(get_part_2_answer(&input), "start-of-message");
+
+    Ok(format!(
         r#"
 Part 1: packet start index: {}
 Part 2: packet start index: {}
 "#,
         part_1_answer, part_2_answer,
-    )
+    ))
 }
 
 #[cfg(test)]
 mod test {
+    extern crate test;
+
+    use std::{
+        collections::HashSet,
+        io::{BufReader, Read},
+    };
+
     use super::*;
+    use crate::rng::XorShift;
     use rstest::*;
+    use test::Bencher;
+    use tokio::{io::AsyncWriteExt, net::TcpListener, sync::mpsc};
 
     #[rstest(
         input,
@@ -48,6 +389,229 @@ mod test {
         case("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11)
     )]
     fn test_part_1(input: &str, expected: usize) {
-        assert_eq!(get_start_packet_index(input, 4) + 1, expected);
+        assert_eq!(get_part_1_answer(input), Ok(expected));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 19),
+        case("bvwbjplbgvbhsrlpgdmjqwftvncz", 23),
+        case("nppdvjthqldpwncqszvftbrmjlhg", 23),
+        case("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 29),
+        case("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 26)
+    )]
+    fn test_part_2(input: &str, expected: usize) {
+        assert_eq!(get_part_2_answer(input), Ok(expected));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(
+            "abcabcdabc",
+            "\nPart 1: packet start index: 7\nPart 2: packet start index: no start-of-message marker (needs 14 distinct characters, the longest run is 4, characters 4 to 7)\n"
+        ),
+        case(
+            "aabab",
+            "\nPart 1: packet start index: no start-of-packet marker (needs 4 distinct characters, the longest run is 2, characters 2 to 3)\nPart 2: packet start index: no start-of-message marker (needs 14 distinct characters, the longest run is 2, characters 2 to 3)\n"
+        ),
+        case(
+            "",
+            "\nPart 1: packet start index: no start-of-packet marker (the datastream is empty)\nPart 2: packet start index: no start-of-message marker (the datastream is empty)\n"
+        )
+    )]
+    fn test_no_marker(input: &str, expected: &str) {
+        assert_eq!(solve(input.to_string(), &[]).unwrap(), expected);
+    }
+
+    #[rstest(
+        input,
+        markers,
+        expected,
+        case("aabcdxxefghyy", "p:4", vec!["p marker 1..5, payload 5..6 (1 bytes)", "p marker 6..10, payload 10..13 (3 bytes)"]),
+        case("aabcdxxefghyy", "p:4,q:6,r:14", vec![
+            "p marker 1..5, payload 5..6 (1 bytes)",
+            "p marker 6..10, payload 10..13 (3 bytes)",
+            "q marker 6..12, payload 12..13 (1 bytes)",
+        ]),
+        case("aaaa", "p:2", vec![])
+    )]
+    fn test_decode(input: &str, markers: &str, expected: Vec<&str>) {
+        let markers = markers
+            .split(',')
+            .map(|marker| marker.parse().unwrap())
+            .collect::<Vec<_>>();
+        let frames = decode(input.as_bytes(), &markers)
+            .iter()
+            .map(Frame::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(frames, expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("mjqjpqmgbljsphdztnvjfqwrcgsmlb", (7, 19)),
+        case("bvwbjplbgvbhsrlpgdmjqwftvncz", (5, 23)),
+        case("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", (11, 26))
+    )]
+    fn test_decode_first_markers(input: &str, expected: (usize, usize)) {
+        let frames = decode(input.as_bytes(), &default_markers());
+        let first = |name: &str| {
+            frames
+                .iter()
+                .find(|frame| frame.marker.name == name)
+                .unwrap()
+                .marker
+                .range
+                .end
+        };
+        assert_eq!((first("packet"), first("message")), expected);
+    }
+
+    #[rstest(marker, case("packet"), case("packet:"), case("packet:0"), case(":x"))]
+    fn test_invalid_marker(marker: &str) {
+        assert!(marker.parse::<MarkerDefinition>().is_err());
+    }
+
+    fn markers() -> Vec<MarkerDefinition> {
+        vec!["p:4".parse().unwrap()]
+    }
+
+    /// Fails the test instead of hanging it if a marker never arrives.
+    async fn within_timeout<F: std::future::Future>(future: F) -> F::Output {
+        time::timeout(Duration::from_secs(5), future)
+            .await
+            .expect("timed out waiting for the tail")
+    }
+
+    #[tokio::test]
+    async fn test_tail_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let reader = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            tail(stream, &markers(), None, |marker| {
+                tx.send(marker).unwrap();
+                Ok(())
+            })
+            .await
+        });
+
+        let (mut writer, _) = within_timeout(listener.accept()).await.unwrap();
+        writer.write_all(b"aabcd").await.unwrap();
+        let marker = within_timeout(rx.recv()).await.unwrap();
+        assert_eq!(marker.to_string(), "p marker 1..5");
+        writer.write_all(b"xxefghyy").await.unwrap();
+        let marker = within_timeout(rx.recv()).await.unwrap();
+        assert_eq!(marker.to_string(), "p marker 6..10");
+        drop(writer);
+
+        assert_eq!(within_timeout(reader).await.unwrap().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tail_report_error() {
+        let found = within_timeout(tail(&b"aabcdxxefg"[..], &markers(), None, |_| {
+            Err(miette!("stdout closed"))
+        }))
+        .await;
+        assert_eq!(found.unwrap_err().to_string(), "stdout closed");
+    }
+
+    #[tokio::test]
+    async fn test_tail_file() {
+        let path = std::env::temp_dir().join(format!("day-06-tail-{}.txt", std::process::id()));
+        let mut writer = File::create(&path).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let file = File::open(&path).await.unwrap();
+        let reader = tokio::spawn(async move {
+            let poll = Some(Duration::from_millis(5));
+            tail(file, &markers(), poll, |marker| {
+                tx.send(marker).unwrap();
+                Ok(())
+            })
+            .await
+        });
+
+        let received = within_timeout(async {
+            let mut received = vec![];
+            for chunk in ["aabcd", "xxefg"] {
+                writer.write_all(chunk.as_bytes()).await.unwrap();
+                writer.flush().await.unwrap();
+                received.push(rx.recv().await.unwrap().to_string());
+            }
+            received
+        })
+        .await;
+
+        reader.abort();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(received, ["p marker 1..5", "p marker 6..10"]);
+    }
+
+    /// The original window-by-window search, kept to check and benchmark against.
+    fn hash_set_marker(input: &str, window_size: usize) -> Option<usize> {
+        input
+            .chars()
+            .enumerate()
+            .collect::<Vec<_>>()
+            .windows(window_size)
+            .find(|slice| window_size == slice.iter().map(|(_, v)| v).collect::<HashSet<_>>().len())
+            .map(|slice| slice.last().unwrap().0)
+    }
+
+    /// A deterministic datastream of `len` letters drawn from the first
+    /// `letters` of the alphabet.
+    fn generate_stream(len: usize, letters: u8) -> String {
+        let mut rng = XorShift::default();
+        (0..len)
+            .map(|_| (b'a' + rng.below(letters as u64) as u8) as char)
+            .collect()
+    }
+
+    #[rstest(window, case(1), case(2), case(4), case(8), case(14), case(20))]
+    fn test_matches_hash_set(window: usize) {
+        for letters in [4, 8, 16, 26] {
+            let input = generate_stream(2_000, letters);
+            assert_eq!(
+                find_marker(input.bytes(), window).ok(),
+                hash_set_marker(&input, window)
+            );
+        }
+    }
+
+    #[rstest(
+        input,
+        window,
+        expected,
+        case("abcabcd", 4, Some(6)),
+        case("aaaa", 2, None)
+    )]
+    fn test_byte_stream(input: &str, window: usize, expected: Option<usize>) {
+        let stream = BufReader::new(input.as_bytes()).bytes();
+        assert_eq!(
+            find_marker(stream.map(Result::unwrap), window).ok(),
+            expected
+        );
+    }
+
+    /// A long stream of 13 letters that only has a 14-letter marker at the end.
+    fn late_marker_stream() -> String {
+        generate_stream(100_000, 13) + "abcdefghijklmn"
+    }
+
+    #[bench]
+    fn bench_hash_set(b: &mut Bencher) {
+        let input = late_marker_stream();
+        b.iter(|| hash_set_marker(&input, 14));
+    }
+
+    #[bench]
+    fn bench_last_seen(b: &mut Bencher) {
+        let input = late_marker_stream();
+        b.iter(|| find_marker(input.bytes(), 14));
     }
 }
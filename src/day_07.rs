@@ -1,210 +1,653 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, path::Path, str::FromStr};
 
-struct File {
-    size: u32,
-}
-
-struct Dir {
-    dirs: HashMap<String, Dir>,
-    files: HashMap<String, File>,
-}
-
-impl Default for Dir {
-    fn default() -> Self {
-        Self {
-            dirs: HashMap::new(),
-            files: HashMap::new(),
-        }
-    }
-}
-
-impl Dir {
-    fn get_size(&self) -> u32 {
-        let size_dirs: u32 = self.dirs.values().map(|dir| dir.get_size()).sum();
-        let size_files: u32 = self.files.values().map(|file| file.size).sum();
+use miette::{miette, IntoDiagnostic, Result};
 
-        size_dirs + size_files
-    }
-}
+use crate::{
+    cli::get_option,
+    filesystem::{FileSystem, FsError, NodeId},
+};
 
 #[derive(Debug)]
 enum LsOutput {
     Dir { name: String },
-    File { name: String, size: u32 },
+    File { name: String, size: u64 },
 }
 
 impl LsOutput {
-    fn add_to_dir(&self, dir: &mut Dir) {
+    fn add_to_dir(&self, fs: &mut FileSystem, dir: NodeId) -> Result<NodeId, FsError> {
         match self {
-            Self::Dir { name } => {
-                dir.dirs.insert(name.clone(), Dir::default());
-            }
-            Self::File { name, size } => {
-                dir.files.insert(name.clone(), File { size: size.clone() });
-            }
+            Self::Dir { name } => fs.add_dir(dir, name),
+            Self::File { name, size } => fs.add_file(dir, name, *size),
         }
     }
 }
 
 impl FromStr for LsOutput {
-    type Err = ();
+    type Err = miette::Report;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split_whitespace();
-        let dir_or_size = parts.next().expect("expected dir or size");
-        let name = parts.next().map(String::from).expect("expected name");
+        let (dir_or_size, name) = s
+            .split_once(' ')
+            .filter(|(_, name)| !name.is_empty() && !name.contains('/'))
+            .ok_or_else(|| miette!("expected `dir <name>` or `<size> <name>`"))?;
+        let name = name.to_string();
 
         Ok(match dir_or_size {
             "dir" => LsOutput::Dir { name },
             _ => LsOutput::File {
                 name,
-                size: dir_or_size.parse().expect("expected size is u32"),
+                size: dir_or_size
+                    .parse()
+                    .map_err(|e| miette!("invalid size {:?}: {}", dir_or_size, e))?,
             },
         })
     }
 }
 
 enum Cmd {
-    Cd { name: String },
-    Ls { output: Vec<LsOutput> },
+    Cd {
+        path: String,
+    },
+    /// Lists the working directory, or `path` if given.
+    Ls {
+        path: Option<String>,
+        /// Each entry with the line it was printed on.
+        output: Vec<(usize, LsOutput)>,
+    },
+    Pwd {
+        output: Option<String>,
+    },
+    /// Anything else: skipped, along with its output, with a warning.
+    Unknown {
+        name: String,
+    },
 }
 
 impl FromStr for Cmd {
-    type Err = ();
+    type Err = miette::Report;
 
+    /// Reads the command itself, from the text after `$`. Its output is
+    /// filled in by `parse_history`. The argument is the rest of the line,
+    /// less surrounding whitespace, so names with spaces in them need no
+    /// quoting: `cd a b` goes into a directory called `a b` rather than
+    /// being rejected for having two arguments.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.trim().lines();
-        let mut cmd_and_arg = lines
-            .next()
-            .expect("expected first line is command")
-            .split_whitespace();
-
-        Ok(
-            match cmd_and_arg.next().expect("cmd must have executable name") {
-                "cd" => Cmd::Cd {
-                    name: cmd_and_arg
-                        .next()
-                        .expect("cmd must have name arg")
-                        .to_string(),
-                },
-                "ls" => Cmd::Ls {
-                    output: lines
-                        .map(|line| line.parse().expect("ls output must parse"))
-                        .collect(),
-                },
-                cmd => panic!("unrecognized command: {}", cmd),
+        let s = s.trim();
+        let (name, arg) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(miette!("expected a command after `$`"));
+        }
+        let arg = Some(arg.trim())
+            .filter(|arg| !arg.is_empty())
+            .map(String::from);
+
+        Ok(match name {
+            "cd" => Cmd::Cd {
+                path: arg.ok_or_else(|| miette!("`cd` needs a directory"))?,
             },
-        )
+            "ls" => Cmd::Ls {
+                path: arg,
+                output: vec![],
+            },
+            "pwd" => Cmd::Pwd { output: None },
+            _ => Cmd::Unknown {
+                name: name.to_string(),
+            },
+        })
     }
 }
 
-fn parse_history(input: &str) -> Vec<Cmd> {
-    input
-        .split('$')
-        .skip(1) // this should be `cd /`
-        .map(Cmd::from_str)
-        .map(|cmd| cmd.expect("expected cmd to parse"))
-        .collect()
+/// A command and the line of the transcript it was typed on.
+struct Entry {
+    line: usize,
+    cmd: Cmd,
 }
 
-fn parse_cmds(cmds: Vec<Cmd>) -> Dir {
-    let mut root = Dir::default();
-    let mut path = vec![];
+fn parse_history(input: &str) -> Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = vec![];
 
-    for cmd in cmds {
-        match cmd {
-            Cmd::Cd { name } if name == ".." => {
-                path.pop().expect("expected a parent dir");
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(cmd) = line.strip_prefix('$') {
+            let cmd = cmd
+                .parse()
+                .map_err(|e| miette!("{} - line {}: {:?}", e, i + 1, line))?;
+            entries.push(Entry { line: i + 1, cmd });
+            continue;
+        }
+
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| miette!("line {}: output before any command: {:?}", i + 1, line))?;
+        match &mut entry.cmd {
+            Cmd::Ls { output, .. } => output.push((
+                i + 1,
+                line.parse()
+                    .map_err(|e| miette!("{} - line {}: {:?}", e, i + 1, line))?,
+            )),
+            Cmd::Pwd {
+                output: output @ None,
+            } => *output = Some(line.to_string()),
+            Cmd::Unknown { .. } => {}
+            Cmd::Cd { .. } | Cmd::Pwd { .. } => {
+                return Err(miette!(
+                    "line {}: unexpected output from line {}: {:?}",
+                    i + 1,
+                    entry.line,
+                    line
+                ))
             }
-            Cmd::Cd { name } => {
-                path.push(name);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn find_dir(fs: &FileSystem, cwd: NodeId, line: usize, cmd: &str, path: &str) -> Result<NodeId> {
+    let dir = fs
+        .resolve(cwd, path)
+        .map_err(|e| miette!("line {}: {} {}: {}", line, cmd, path, e))?;
+    if !fs.is_dir(dir) {
+        return Err(miette!(
+            "line {}: {} {}: not a directory: {}",
+            line,
+            cmd,
+            path,
+            fs.path(dir)
+        ));
+    }
+    Ok(dir)
+}
+
+/// Replays the transcript into a filesystem. Every directory `cd` or `ls`
+/// visits must have been listed by an earlier `ls`. Anything odd that doesn't
+/// stop the replay is returned as a warning.
+fn parse_cmds(entries: Vec<Entry>) -> Result<(FileSystem, Vec<String>)> {
+    let mut fs = FileSystem::default();
+    let mut cwd = fs.root();
+    let mut warnings = vec![];
+
+    for Entry { line, cmd } in entries {
+        match cmd {
+            Cmd::Cd { path } => {
+                cwd = find_dir(&fs, cwd, line, "cd", &path)?;
             }
-            Cmd::Ls { output } => {
-                let cwd = path.iter().skip(1).fold(&mut root, |dir, name| {
-                    dir.dirs.get_mut(name).expect("expected dir to exist")
-                });
-                output.iter().for_each(|line| line.add_to_dir(cwd));
+            Cmd::Ls { path, output } => {
+                let dir = match path {
+                    Some(path) => find_dir(&fs, cwd, line, "ls", &path)?,
+                    None => cwd,
+                };
+                for (line, entry) in output {
+                    entry
+                        .add_to_dir(&mut fs, dir)
+                        .map_err(|e| miette!("line {}: {}", line, e))?;
+                }
             }
+            Cmd::Pwd { output } => match output {
+                Some(output) if output != fs.path(cwd) => warnings.push(format!(
+                    "line {}: pwd printed {} but the transcript is in {}",
+                    line,
+                    output,
+                    fs.path(cwd)
+                )),
+                _ => {}
+            },
+            Cmd::Unknown { name } => warnings.push(format!(
+                "line {}: unknown command `{}`, skipped",
+                line, name
+            )),
         }
     }
 
-    root
+    Ok((fs, warnings))
 }
 
-fn get_sizes(name: String, dir: &Dir) -> Vec<(String, u32)> {
-    let mut subdir_sizes: Vec<(String, u32)> = dir
-        .dirs
+fn get_dirs_sum_lte_threshold(fs: &FileSystem, threshold: u64) -> u64 {
+    fs.dirs()
+        .map(|dir| fs.size(dir))
+        .filter(|&size| size <= threshold)
+        .sum()
+}
+
+fn parse_fs(input: &str) -> Result<(FileSystem, Vec<String>)> {
+    parse_cmds(parse_history(input)?)
+}
+
+fn get_part_1_answer(fs: &FileSystem) -> u64 {
+    get_dirs_sum_lte_threshold(fs, 100_000)
+}
+
+fn get_part_2_answer(fs: &FileSystem) -> Result<u64> {
+    DeletionPlanner::new(fs, 70_000_000, 30_000_000)?
+        .including_root()
+        .smallest_dir()
+        .map(|deletion| deletion.freed)
+        .ok_or_else(|| miette!("no single directory frees enough space"))
+}
+
+/// A `$ cd`/`$ ls` transcript that `parse_history` rebuilds `fs` from: each
+/// directory is listed, then visited in name order. Names with line breaks or
+/// surrounding whitespace don't survive the trip.
+fn export_transcript(fs: &FileSystem) -> String {
+    fn visit(fs: &FileSystem, dir: NodeId, lines: &mut Vec<String>) {
+        lines.push("$ ls".to_string());
+        for child in fs.children(dir) {
+            lines.push(match fs.is_dir(child) {
+                true => format!("dir {}", fs.name(child)),
+                false => format!("{} {}", fs.size(child), fs.name(child)),
+            });
+        }
+        for child in fs.children(dir).filter(|&child| fs.is_dir(child)) {
+            lines.push(format!("$ cd {}", fs.name(child)));
+            visit(fs, child, lines);
+            lines.push("$ cd ..".to_string());
+        }
+    }
+
+    let mut lines = vec!["$ cd /".to_string()];
+    visit(fs, fs.root(), &mut lines);
+    lines.join("\n")
+}
+
+/// The tree the transcript describes, in the puzzle's own notation, with
+/// directory sizes filled in.
+fn format_tree(fs: &FileSystem) -> String {
+    fs.iter()
+        .map(|id| {
+            let kind = if fs.is_dir(id) { "dir" } else { "file" };
+            let name = if id == fs.root() { "/" } else { fs.name(id) };
+            format!(
+                "{:indent$}- {} ({}, size={})\n",
+                "",
+                name,
+                kind,
+                fs.size(id),
+                indent = 2 * fs.depth(id)
+            )
+        })
+        .collect()
+}
+
+/// Every directory with its total size, largest first, like `du | sort -rn`.
+fn du(fs: &FileSystem) -> Vec<(u64, String)> {
+    let mut sizes = fs
+        .dirs()
+        .map(|dir| (fs.size(dir), fs.path(dir)))
+        .collect::<Vec<_>>();
+    sizes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    sizes
+}
+
+/// The `count` largest files, ties broken by path.
+fn largest_files(fs: &FileSystem, count: usize) -> Vec<(u64, String)> {
+    let mut files = fs
+        .files()
+        .map(|file| (fs.size(file), fs.path(file)))
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    files.truncate(count);
+    files
+}
+
+/// How much file data sits at each depth below the root.
+#[derive(Debug, PartialEq, Eq)]
+struct DepthSizes {
+    depth: usize,
+    files: usize,
+    size: u64,
+}
+
+fn depth_histogram(fs: &FileSystem) -> Vec<DepthSizes> {
+    let mut depths: Vec<DepthSizes> = vec![];
+    for file in fs.files() {
+        let depth = fs.depth(file);
+        while depths.len() <= depth {
+            depths.push(DepthSizes {
+                depth: depths.len(),
+                files: 0,
+                size: 0,
+            });
+        }
+        depths[depth].files += 1;
+        depths[depth].size += fs.size(file);
+    }
+    depths
+}
+
+fn format_histogram(depths: &[DepthSizes]) -> String {
+    const WIDTH: u128 = 40;
+    let max = depths
         .iter()
-        .map(|(name, dir)| get_sizes(name.to_owned(), dir))
-        .flatten()
-        .collect();
+        .map(|depth| depth.size)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    depths
+        .iter()
+        .map(|depth| {
+            let bar = (depth.size as u128 * WIDTH / max as u128) as usize;
+            format!(
+                "{:>3} {:>6} files {:>12} {}\n",
+                depth.depth,
+                depth.files,
+                depth.size,
+                "#".repeat(bar)
+            )
+        })
+        .collect()
+}
 
-    subdir_sizes.insert(0, (name, dir.get_size()));
+fn format_sizes(sizes: &[(u64, String)]) -> String {
+    sizes
+        .iter()
+        .map(|(size, path)| format!("{}\t{}\n", size, path))
+        .collect()
+}
 
-    subdir_sizes
+/// Some directories or files whose deletion frees `freed` bytes.
+#[derive(Debug, PartialEq, Eq)]
+struct Deletion {
+    nodes: Vec<NodeId>,
+    freed: u64,
 }
 
-fn get_dirs_sum_lte_threshold(dir: &Dir, threshold: u32) -> u32 {
-    let lte_threshold = get_sizes("/".to_string(), dir)
-        .into_iter()
-        .filter_map(
-            |(_, size)| {
-                if size <= threshold {
-                    Some(size)
-                } else {
-                    None
-                }
-            },
-        );
+impl Deletion {
+    fn new(fs: &FileSystem, nodes: Vec<NodeId>) -> Self {
+        let freed = nodes.iter().map(|&id| fs.size(id)).sum();
+        Self { nodes, freed }
+    }
+}
 
-    lte_threshold.sum()
+/// Ways to get `need` bytes free on a disk of `disk` bytes. The root itself
+/// is only offered for deletion if asked for with `including_root`.
+struct DeletionPlanner<'a> {
+    fs: &'a FileSystem,
+    disk: u64,
+    need: u64,
+    free: u64,
+    include_root: bool,
 }
 
-fn parse_fs(input: &str) -> Dir {
-    parse_cmds(parse_history(input))
+impl<'a> DeletionPlanner<'a> {
+    fn new(fs: &'a FileSystem, disk: u64, need: u64) -> Result<Self> {
+        let used = fs.size(fs.root());
+        let free = disk
+            .checked_sub(used)
+            .ok_or_else(|| miette!("used space {} is more than the disk size {}", used, disk))?;
+        Ok(Self {
+            fs,
+            disk,
+            need,
+            free,
+            include_root: false,
+        })
+    }
+
+    /// Lets the root count as a directory to delete, as the puzzle does.
+    fn including_root(self) -> Self {
+        Self {
+            include_root: true,
+            ..self
+        }
+    }
+
+    fn free(&self) -> u64 {
+        self.free
+    }
+
+    /// How much has to go; zero if there's already enough space.
+    fn to_free(&self) -> u64 {
+        self.need.saturating_sub(self.free())
+    }
+
+    /// The smallest single directory that frees enough.
+    fn smallest_dir(&self) -> Option<Deletion> {
+        let to_free = self.to_free();
+        if to_free == 0 {
+            return Some(Deletion::new(self.fs, vec![]));
+        }
+        self.fs
+            .dirs()
+            .filter(|&dir| self.include_root || dir != self.fs.root())
+            .filter(|&dir| self.fs.size(dir) >= to_free)
+            .min_by_key(|&dir| self.fs.size(dir))
+            .map(|dir| Deletion::new(self.fs, vec![dir]))
+    }
+
+    /// The fewest directories, none inside another, that free enough. Of
+    /// those, the set that frees the most.
+    fn fewest_dirs(&self) -> Option<Deletion> {
+        let to_free = self.to_free();
+        let mut choices = HashMap::new();
+        let best = self.largest_by_count(self.fs.root(), &mut choices);
+        let count = best.iter().position(|&freed| freed >= to_free)?;
+
+        let mut dirs = vec![];
+        collect_choice(&choices, self.fs.root(), count, &mut dirs);
+        dirs.reverse();
+        Some(Deletion::new(self.fs, dirs))
+    }
+
+    /// For each count `k`, the most that `k` directories under `dir`, none
+    /// inside another, can free. How each best count was reached is recorded in
+    /// `choices` so the directories can be found again afterwards.
+    fn largest_by_count(&self, dir: NodeId, choices: &mut HashMap<NodeId, Choice>) -> Vec<u64> {
+        let mut best = vec![0];
+        let mut splits = vec![];
+        for child in self.fs.children(dir).filter(|&child| self.fs.is_dir(child)) {
+            let child_best = self.largest_by_count(child, choices);
+            let (merged, split) = merge_by_count(&best, &child_best);
+            best = merged;
+            splits.push((child, split));
+        }
+
+        let mut whole = false;
+        if dir != self.fs.root() || self.include_root {
+            let size = self.fs.size(dir);
+            match best.get_mut(1) {
+                Some(one) if *one >= size => {}
+                Some(one) => (*one, whole) = (size, true),
+                None => (best, whole) = (vec![0, size], true),
+            }
+        }
+        choices.insert(dir, Choice { splits, whole });
+        best
+    }
+
+    /// The fewest files that free enough: the largest ones first.
+    fn fewest_files(&self) -> Option<Deletion> {
+        let to_free = self.to_free();
+        let mut files = self.fs.files().collect::<Vec<_>>();
+        files.sort_by_key(|&file| std::cmp::Reverse(self.fs.size(file)));
+
+        let mut freed = 0;
+        let count = files
+            .iter()
+            .position(|&file| {
+                let enough = freed >= to_free;
+                freed += self.fs.size(file);
+                enough
+            })
+            .or((freed >= to_free).then_some(files.len()))?;
+        files.truncate(count);
+        Some(Deletion::new(self.fs, files))
+    }
 }
 
-fn get_part_1_answer(dir: &Dir) -> u32 {
-    get_dirs_sum_lte_threshold(dir, 100_000)
+/// How `largest_by_count` reached its best for each count under a directory.
+struct Choice {
+    /// For each subdirectory, in the order they were merged, how many of each
+    /// count went to it.
+    splits: Vec<(NodeId, Vec<usize>)>,
+    /// Whether a count of one is best spent on the directory itself.
+    whole: bool,
 }
 
-fn get_part_2_answer(dir: &Dir) -> u32 {
-    let total_fs_size: u32 = 70_000_000;
-    let needs_fs_size: u32 = 30_000_000;
+/// Combines the best picks of two disjoint subtrees, count by count, along
+/// with how many of each combined count came from `b`.
+fn merge_by_count(a: &[u64], b: &[u64]) -> (Vec<u64>, Vec<usize>) {
+    let mut merged: Vec<u64> = vec![];
+    let mut from_b = vec![];
+    for (i, a_freed) in a.iter().enumerate() {
+        for (j, b_freed) in b.iter().enumerate() {
+            let freed = a_freed + b_freed;
+            match merged.get_mut(i + j) {
+                Some(best) if *best >= freed => {}
+                Some(best) => (*best, from_b[i + j]) = (freed, j),
+                None => {
+                    merged.push(freed);
+                    from_b.push(j);
+                }
+            }
+        }
+    }
+    (merged, from_b)
+}
 
-    let curr_fs_free = total_fs_size - dir.get_size();
-    let clean_fs_size = needs_fs_size - curr_fs_free;
+/// Follows the recorded choices to find the `count` directories under `dir`,
+/// last first.
+fn collect_choice(
+    choices: &HashMap<NodeId, Choice>,
+    dir: NodeId,
+    mut count: usize,
+    dirs: &mut Vec<NodeId>,
+) {
+    let choice = &choices[&dir];
+    if count == 1 && choice.whole {
+        dirs.push(dir);
+        return;
+    }
+    for (child, split) in choice.splits.iter().rev() {
+        let child_count = split[count];
+        collect_choice(choices, *child, child_count, dirs);
+        count -= child_count;
+    }
+}
 
-    get_sizes("/".to_string(), dir)
+fn format_plans(planner: &DeletionPlanner) -> String {
+    let fs = planner.fs;
+    let plans = [
+        ("Smallest directory", planner.smallest_dir()),
+        ("Fewest directories", planner.fewest_dirs()),
+        ("Fewest files", planner.fewest_files()),
+    ];
+    let plans = plans
         .into_iter()
-        .filter_map(|(_, size)| {
-            if size >= clean_fs_size {
-                Some(size)
-            } else {
-                None
+        .map(|(name, plan)| match plan {
+            Some(Deletion { nodes, freed }) => {
+                let paths = nodes
+                    .iter()
+                    .map(|&id| format!("  {}\n", fs.path(id)))
+                    .collect::<String>();
+                format!(
+                    "{}: frees {}, leaving {} free\n{}",
+                    name,
+                    freed,
+                    planner.free() + freed,
+                    paths
+                )
             }
+            None => format!("{}: can't free enough\n", name),
         })
-        .min()
-        .expect("expected min freeable dir")
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "{} of {} free, {} needed: delete at least {}\n\n{}",
+        planner.free(),
+        planner.disk,
+        planner.need,
+        planner.to_free(),
+        plans
+    )
+}
+
+/// Answers the `tree`, `du`, `top [count]`, `histogram`, `transcript` and
+/// `plan [--disk size] [--need size]` subcommands.
+fn report(fs: &FileSystem, query: &[String]) -> Result<String> {
+    let report = match query[0].as_str() {
+        "tree" => format_tree(fs),
+        "du" => format_sizes(&du(fs)),
+        "top" => {
+            let count = query
+                .get(1)
+                .map(|count| count.parse::<usize>())
+                .transpose()
+                .into_diagnostic()?
+                .unwrap_or(10);
+            format_sizes(&largest_files(fs, count))
+        }
+        "histogram" => format_histogram(&depth_histogram(fs)),
+        "transcript" => export_transcript(fs),
+        "plan" => {
+            let size = |name: &str, default: u64| {
+                get_option(query, name)
+                    .map(str::parse::<u64>)
+                    .transpose()
+                    .into_diagnostic()
+                    .map(|size| size.unwrap_or(default))
+            };
+            let planner =
+                DeletionPlanner::new(fs, size("--disk", 70_000_000)?, size("--need", 30_000_000)?)?;
+            format_plans(&planner)
+        }
+        report => return Err(miette!("unknown report: {}", report)),
+    };
+    Ok(report)
+}
+
+/// Reads the real directory at `source` instead of a transcript.
+pub(crate) fn import(source: &str, options: &[String]) -> Result<String> {
+    let (fs, warnings) = FileSystem::import(Path::new(source))
+        .map_err(|e| miette!("can't import {}: {}", source, e))?;
+    answer(&fs, warnings, options)
 }
 
-pub(crate) fn solve(input: String) -> String {
-    let dir = parse_fs(&input);
-    let part_1_answer = get_part_1_answer(&dir);
-    let part_2_answer = get_part_2_answer(&dir);
+pub(crate) fn solve(input: String, options: &[String]) -> Result<String> {
+    let (fs, warnings) = parse_fs(&input)?;
+    answer(&fs, warnings, options)
+}
 
-    format!(
-        r#"
+/// Warnings come before every report except `transcript`, which is meant to
+/// be saved and replayed as it is.
+fn answer(fs: &FileSystem, warnings: Vec<String>, options: &[String]) -> Result<String> {
+    const REPORTS: [&str; 6] = ["tree", "du", "top", "histogram", "plan", "transcript"];
+    let warnings = warnings
+        .iter()
+        .map(|warning| format!("warning: {}\n", warning))
+        .collect::<String>();
+
+    if let Some(i) = options
+        .iter()
+        .position(|option| REPORTS.contains(&option.as_str()))
+    {
+        let query = &options[i..];
+        return Ok(match query[0].as_str() {
+            "transcript" => report(fs, query)?,
+            _ => format!("{}\n{}", warnings, report(fs, query)?),
+        });
+    }
+
+    let part_1_answer = get_part_1_answer(fs);
+    let part_2_answer = get_part_2_answer(fs)?;
+
+    Ok(format!(
+        r#"{}
 Part 1: sum dirs under 100_000 threshold: {}
 Part 2: smallest deletable dir to free size: {}
 "#,
-        part_1_answer, part_2_answer,
-    )
+        warnings, part_1_answer, part_2_answer,
+    ))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::rng::XorShift;
     use rstest::*;
 
     static INPUT: &str = "$ cd /
@@ -232,12 +675,368 @@ mod test {
     7214296 k";
 
     #[rstest(input, expected, case(INPUT, 95_437))]
-    fn test_part_1(input: &str, expected: u32) {
-        assert_eq!(get_part_1_answer(&parse_fs(input)), expected);
+    fn test_part_1(input: &str, expected: u64) {
+        assert_eq!(get_part_1_answer(&parse_fs(input).unwrap().0), expected);
     }
 
     #[rstest(input, expected, case(INPUT, 24_933_642))]
-    fn test_part_2(input: &str, expected: u32) {
-        assert_eq!(get_part_2_answer(&parse_fs(input)), expected);
+    fn test_part_2(input: &str, expected: u64) {
+        assert_eq!(
+            get_part_2_answer(&parse_fs(input).unwrap().0).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_shell_commands() {
+        let input = "$ cd /
+            $ ls
+            dir a
+            dir d
+            $ ls a
+            dir e
+            29116 f
+            $ cd a/e/../e
+            $ pwd
+            /a/e
+            $ ls
+            584 i
+            $ cd /
+            $ ls /d
+            4060174 j
+            $ cd d
+            $ pwd
+            /a
+            $ rm j
+            removed 'j'
+            $ cd ../a/e";
+        let (fs, warnings) = parse_fs(input).unwrap();
+        assert_eq!(
+            fs.files().map(|id| fs.path(id)).collect::<Vec<_>>(),
+            ["/a/e/i", "/a/f", "/d/j"]
+        );
+        assert_eq!(
+            warnings,
+            [
+                "line 17: pwd printed /a but the transcript is in /d",
+                "line 19: unknown command `rm`, skipped"
+            ]
+        );
+    }
+
+    #[rstest(
+        query,
+        expected,
+        case("tree", "warning: line 3: unknown command `rm`, skipped\n\n- / (dir, size=1)\n  - b (file, size=1)\n"),
+        case("du", "warning: line 3: unknown command `rm`, skipped\n\n"),
+        case("transcript", "$ cd /\n$ ls\n1 b")
+    )]
+    fn test_report_warnings(query: &str, expected: &str) {
+        let (fs, warnings) = parse_fs("$ ls\n1 b\n$ rm b").unwrap();
+        let output = answer(&fs, warnings, &[query.to_string()]).unwrap();
+        assert!(output.starts_with(expected), "{:?}", output);
+    }
+
+    #[test]
+    fn test_tree() {
+        let fs = parse_fs(INPUT).unwrap().0;
+        let tree = format_tree(&fs);
+        assert_eq!(
+            tree.lines().take(4).collect::<Vec<_>>(),
+            [
+                "- / (dir, size=48381165)",
+                "  - a (dir, size=94853)",
+                "    - e (dir, size=584)",
+                "      - i (file, size=584)"
+            ]
+        );
+        assert_eq!(tree.lines().count(), 14);
+    }
+
+    #[test]
+    fn test_du_and_largest_files() {
+        let fs = parse_fs(INPUT).unwrap().0;
+        assert_eq!(
+            du(&fs),
+            [
+                (48_381_165, "/".to_string()),
+                (24_933_642, "/d".to_string()),
+                (94_853, "/a".to_string()),
+                (584, "/a/e".to_string())
+            ]
+        );
+        assert_eq!(
+            largest_files(&fs, 2),
+            [
+                (14_848_514, "/b.txt".to_string()),
+                (8_504_156, "/c.dat".to_string())
+            ]
+        );
+        assert_eq!(largest_files(&fs, 100).len(), 10);
+    }
+
+    #[test]
+    fn test_depth_histogram() {
+        let fs = parse_fs(INPUT).unwrap().0;
+        let depths = depth_histogram(&fs);
+        assert_eq!(
+            depths
+                .iter()
+                .map(|depth| (depth.files, depth.size))
+                .collect::<Vec<_>>(),
+            [(0, 0), (2, 23_352_670), (7, 25_027_911), (1, 584)]
+        );
+        let histogram = format_histogram(&depths);
+        assert!(histogram.lines().nth(2).unwrap().ends_with(&"#".repeat(40)));
+    }
+
+    #[rstest(
+        disk,
+        need,
+        smallest,
+        fewest_dirs,
+        fewest_files,
+        case(70_000_000, 30_000_000, Some(vec!["/d"]), Some(vec!["/d"]), Some(vec!["/b.txt"])),
+        case(48_400_000, 100_000, Some(vec!["/a"]), Some(vec!["/d"]), Some(vec!["/b.txt"])),
+        case(48_400_000, 25_000_000, None, Some(vec!["/a", "/d"]), Some(vec!["/b.txt", "/c.dat", "/d/d.log"])),
+        case(48_400_000, 50_000_000, None, None, None),
+        case(60_000_000, 1, Some(vec![]), Some(vec![]), Some(vec![]))
+    )]
+    fn test_deletion_planner(
+        disk: u64,
+        need: u64,
+        smallest: Option<Vec<&str>>,
+        fewest_dirs: Option<Vec<&str>>,
+        fewest_files: Option<Vec<&str>>,
+    ) {
+        let fs = parse_fs(INPUT).unwrap().0;
+        let planner = DeletionPlanner::new(&fs, disk, need).unwrap();
+        let paths = |plan: Option<Deletion>| {
+            plan.map(|plan| {
+                assert!(planner.free() + plan.freed >= need);
+                plan.nodes.iter().map(|&id| fs.path(id)).collect::<Vec<_>>()
+            })
+        };
+        let strings = |paths: Option<Vec<&str>>| {
+            paths.map(|paths| paths.iter().map(|path| path.to_string()).collect())
+        };
+        assert_eq!(paths(planner.smallest_dir()), strings(smallest));
+        assert_eq!(paths(planner.fewest_dirs()), strings(fewest_dirs));
+        assert_eq!(paths(planner.fewest_files()), strings(fewest_files));
+    }
+
+    #[test]
+    fn test_only_root_frees_enough() {
+        let fs = parse_fs(INPUT).unwrap().0;
+        let planner = DeletionPlanner::new(&fs, 70_000_000, 50_000_000).unwrap();
+        assert_eq!(planner.smallest_dir(), None);
+        let planner = planner.including_root();
+        assert_eq!(
+            planner.smallest_dir().map(|plan| plan.nodes),
+            Some(vec![fs.root()])
+        );
+
+        let input = "$ cd /
+            $ ls
+            45000000 a
+            dir b
+            $ cd b
+            $ ls
+            1 c";
+        let fs = parse_fs(input).unwrap().0;
+        assert_eq!(get_part_2_answer(&fs).unwrap(), 45_000_001);
+    }
+
+    /// A deterministic tree of `files` files of up to `max_size` bytes each,
+    /// spread over `dirs` directories nested at random.
+    fn generate_tree(dirs: usize, files: usize, max_size: u64) -> FileSystem {
+        let mut rng = XorShift::default();
+        let mut fs = FileSystem::default();
+        let mut all_dirs = vec![fs.root()];
+        for i in 0..dirs {
+            let parent = all_dirs[rng.index(all_dirs.len())];
+            all_dirs.push(fs.add_dir(parent, &format!("d{}", i)).unwrap());
+        }
+        for i in 0..files {
+            let parent = all_dirs[rng.index(all_dirs.len())];
+            fs.add_file(parent, &format!("f{}", i), rng.below(max_size) + 1)
+                .unwrap();
+        }
+        fs
+    }
+
+    #[rstest(
+        dirs,
+        files,
+        max_size,
+        case(50, 1_000, 4_000_000_000_000),
+        case(500, 20_000, 2_000_000_000_000)
+    )]
+    fn test_terabyte_trees(dirs: usize, files: usize, max_size: u64) {
+        let fs = generate_tree(dirs, files, max_size);
+        let used = fs.size(fs.root());
+        assert!(used > 1_000_000_000_000_000);
+        assert_eq!(
+            used as u128,
+            fs.files().map(|file| fs.size(file) as u128).sum::<u128>()
+        );
+        let mut below = HashMap::new();
+        for file in fs.files() {
+            for dir in fs.ancestors(file).skip(1) {
+                *below.entry(dir).or_insert(0) += fs.size(file);
+            }
+        }
+        for dir in fs.dirs() {
+            assert_eq!(fs.size(dir), below.get(&dir).copied().unwrap_or(0));
+        }
+
+        let disk = used + used / 10;
+        let planner = DeletionPlanner::new(&fs, disk, used / 2).unwrap();
+        for plan in [planner.fewest_dirs(), planner.fewest_files()] {
+            let plan = plan.unwrap();
+            assert!(planner.free() + plan.freed >= used / 2);
+            assert!(planner.free() + plan.freed <= disk);
+        }
+        let dirs = planner.fewest_dirs().unwrap().nodes;
+        for &dir in &dirs {
+            assert!(fs.ancestors(dir).skip(1).all(|id| !dirs.contains(&id)));
+        }
+        assert!(DeletionPlanner::new(&fs, used - 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_size_diagnostics() {
+        let fs = parse_fs("$ ls\n5000000000000 a").unwrap().0;
+        assert_eq!(
+            DeletionPlanner::new(&fs, 4_000_000_000_000, 0)
+                .err()
+                .unwrap()
+                .to_string(),
+            "used space 5000000000000 is more than the disk size 4000000000000"
+        );
+        assert_eq!(
+            get_part_2_answer(&fs).unwrap_err().to_string(),
+            "used space 5000000000000 is more than the disk size 70000000"
+        );
+        assert_eq!(
+            parse_fs("$ ls\n18446744073709551615 a\n1 b")
+                .unwrap_err()
+                .to_string(),
+            "line 3: total size would no longer fit in 64 bits: /b"
+        );
+    }
+
+    #[test]
+    fn test_export_sample() {
+        let fs = parse_fs(INPUT).unwrap().0;
+        let transcript = export_transcript(&fs);
+        assert_eq!(
+            transcript.lines().take(9).collect::<Vec<_>>(),
+            [
+                "$ cd /",
+                "$ ls",
+                "dir a",
+                "14848514 b.txt",
+                "8504156 c.dat",
+                "dir d",
+                "$ cd a",
+                "$ ls",
+                "dir e"
+            ]
+        );
+        assert_eq!(get_part_1_answer(&parse_fs(&transcript).unwrap().0), 95_437);
+    }
+
+    #[rstest(dirs, files, case(0, 0), case(1, 3), case(200, 5_000))]
+    fn test_transcript_round_trip(dirs: usize, files: usize) {
+        let fs = generate_tree(dirs, files, 4_000_000_000_000);
+        let (parsed, warnings) = parse_fs(&export_transcript(&fs)).unwrap();
+        assert!(warnings.is_empty());
+
+        let sizes = |fs: &FileSystem| {
+            fs.iter()
+                .map(|id| (fs.path(id), fs.is_dir(id), fs.size(id)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(&parsed), sizes(&fs));
+        assert_eq!(export_transcript(&parsed), export_transcript(&fs));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("cd a", Some("a")),
+        case("  cd   a b  ", Some("a b")),
+        case("cd\tMy Documents", Some("My Documents")),
+        case("ls a  b", Some("a  b")),
+        case("ls", None),
+        case("ls   ", None)
+    )]
+    fn test_cmd_argument(input: &str, expected: Option<&str>) {
+        let path = match input.parse::<Cmd>().unwrap() {
+            Cmd::Cd { path } => Some(path),
+            Cmd::Ls { path, .. } => path,
+            _ => unreachable!(),
+        };
+        assert_eq!(path.as_deref(), expected);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("", "expected a command after `$`"),
+        case("cd", "`cd` needs a directory"),
+        case("cd   ", "`cd` needs a directory")
+    )]
+    fn test_cmd_errors(input: &str, expected: &str) {
+        assert_eq!(input.parse::<Cmd>().err().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn test_names_with_spaces() {
+        let mut fs = FileSystem::default();
+        let dir = fs.add_dir(fs.root(), "My Documents").unwrap();
+        fs.add_file(dir, "notes for day 7.txt", 42).unwrap();
+        let parsed = parse_fs(&export_transcript(&fs)).unwrap().0;
+        assert_eq!(
+            parsed
+                .lookup("/My Documents/notes for day 7.txt")
+                .map(|id| parsed.size(id)),
+            Ok(42)
+        );
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("$ cd /\n$ cd a", "line 2: cd a: no such file or directory: /a"),
+        case("$ ls\n1 b\n$ cd b", "line 3: cd b: not a directory: /b"),
+        case(
+            "$ ls\ndir a\n$ ls a/b",
+            "line 3: ls a/b: no such file or directory: /a/b"
+        ),
+        case(
+            "$ ls\ndir a\n1 a",
+            "line 3: already exists as a different kind of node: /a"
+        ),
+        case(
+            "$ ls\ndir a\n\n1 a",
+            "line 4: already exists as a different kind of node: /a"
+        ),
+        case("dir a", "line 1: output before any command: \"dir a\""),
+        case("$ cd /\n/", "line 2: unexpected output from line 1: \"/\""),
+        case(
+            "$ ls\nbig a",
+            "invalid size \"big\": invalid digit found in string - line 2: \"big a\""
+        ),
+        case(
+            "$ ls\n1 a/b",
+            "expected `dir <name>` or `<size> <name>` - line 2: \"1 a/b\""
+        ),
+        case("$ cd", "`cd` needs a directory - line 1: \"$ cd\""),
+        case("$", "expected a command after `$` - line 1: \"$\"")
+    )]
+    fn test_transcript_errors(input: &str, expected: &str) {
+        assert_eq!(parse_fs(input).unwrap_err().to_string(), expected);
     }
 }
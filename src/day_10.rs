@@ -11,10 +11,10 @@ enum Instruction {
 }
 
 impl Instruction {
-    fn into_operations(&self) -> Vec<Operation> {
+    fn to_operations(&self) -> Vec<Operation> {
         match self {
             Self::Noop => vec![Operation::Wait],
-            Self::Addx(v) => vec![Operation::Wait, Operation::Add(v.clone())],
+            Self::Addx(v) => vec![Operation::Wait, Operation::Add(*v)],
         }
     }
 }
@@ -23,7 +23,7 @@ impl FromStr for Instruction {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.trim().split_whitespace();
+        let mut parts = s.split_whitespace();
         let instruction = match parts.next().expect("expected operation name") {
             "noop" => Self::Noop,
             "addx" => Self::Addx(
@@ -56,7 +56,7 @@ fn execute_with_midcycle_sampler<F: FnMut(usize, &i32)>(
     let mut mem = 1;
 
     for instruction in instructions {
-        for operation in instruction.into_operations() {
+        for operation in instruction.to_operations() {
             cycle += 1;
             sampler(cycle, &mem);
             match operation {
@@ -71,7 +71,7 @@ fn solve_part_1(instructions: &Vec<Instruction>) -> i32 {
     let mut total: i32 = 0;
 
     execute_with_midcycle_sampler(instructions, |cycle, value| {
-        if cycle >= 20 && cycle <= 220 && (cycle - 20) % 40 == 0 {
+        if (20..=220).contains(&cycle) && (cycle - 20) % 40 == 0 {
             let signal_strength = (cycle as i32) * value;
             total += signal_strength;
         }
@@ -0,0 +1,516 @@
+// An in-memory filesystem tree, for puzzles that rebuild one from a transcript.
+
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// A node in a `FileSystem`. Only meaningful for the filesystem that made it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct NodeId(usize);
+
+#[derive(Debug)]
+enum NodeKind {
+    Dir { children: BTreeMap<String, NodeId> },
+    File { size: u64 },
+}
+
+#[derive(Debug)]
+struct Node {
+    name: String,
+    parent: Option<NodeId>,
+    kind: NodeKind,
+    /// The total size under a directory, until something is added below it.
+    size: Cell<Option<u64>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum FsError {
+    NotFound { path: String },
+    NotADirectory { path: String },
+    AlreadyExists { path: String },
+    TooLarge { path: String },
+}
+
+impl Display for FsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound { path } => write!(f, "no such file or directory: {}", path),
+            Self::NotADirectory { path } => write!(f, "not a directory: {}", path),
+            Self::AlreadyExists { path } => {
+                write!(f, "already exists as a different kind of node: {}", path)
+            }
+            Self::TooLarge { path } => {
+                write!(f, "total size would no longer fit in 64 bits: {}", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FsError {}
+
+/// Directories and files stored in one `Vec`, each pointing at its parent, so
+/// any node can be reached by id and walked up to the root without borrowing
+/// its ancestors.
+#[derive(Debug)]
+pub(crate) struct FileSystem {
+    nodes: Vec<Node>,
+    /// The size of the root, kept up to date so that no directory's size can
+    /// overflow: every one of them is at most this.
+    total: u64,
+}
+
+impl Default for FileSystem {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {
+                name: String::new(),
+                parent: None,
+                kind: NodeKind::Dir {
+                    children: BTreeMap::new(),
+                },
+                size: Cell::new(None),
+            }],
+            total: 0,
+        }
+    }
+}
+
+impl FileSystem {
+    pub(crate) fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub(crate) fn name(&self, id: NodeId) -> &str {
+        &self.node(id).name
+    }
+
+    pub(crate) fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub(crate) fn is_dir(&self, id: NodeId) -> bool {
+        matches!(self.node(id).kind, NodeKind::Dir { .. })
+    }
+
+    /// A directory's entries in name order; nothing for a file.
+    pub(crate) fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let children = match &self.node(id).kind {
+            NodeKind::Dir { children } => Some(children.values().copied()),
+            NodeKind::File { .. } => None,
+        };
+        children.into_iter().flatten()
+    }
+
+    pub(crate) fn child(&self, id: NodeId, name: &str) -> Option<NodeId> {
+        match &self.node(id).kind {
+            NodeKind::Dir { children } => children.get(name).copied(),
+            NodeKind::File { .. } => None,
+        }
+    }
+
+    /// How many directories up the root is.
+    pub(crate) fn depth(&self, id: NodeId) -> usize {
+        self.ancestors(id).count() - 1
+    }
+
+    /// `id` and each directory above it, up to and including the root.
+    pub(crate) fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(id), |&id| self.parent(id))
+    }
+
+    /// The absolute path of `id`, e.g. `/a/e`.
+    pub(crate) fn path(&self, id: NodeId) -> String {
+        if id == self.root() {
+            return "/".to_string();
+        }
+        let mut names = self
+            .ancestors(id)
+            .map(|id| self.name(id))
+            .collect::<Vec<_>>();
+        names.reverse();
+        names.join("/")
+    }
+
+    /// Follows `path` from `cwd`, or from the root if it starts with `/`.
+    /// Understands `.` and `..`; `..` at the root stays at the root.
+    pub(crate) fn resolve(&self, cwd: NodeId, path: &str) -> Result<NodeId, FsError> {
+        let start = if path.starts_with('/') {
+            self.root()
+        } else {
+            cwd
+        };
+        path.split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .try_fold(start, |dir, part| {
+                if !self.is_dir(dir) {
+                    return Err(FsError::NotADirectory {
+                        path: self.path(dir),
+                    });
+                }
+                match part {
+                    ".." => Ok(self.parent(dir).unwrap_or(dir)),
+                    name => self.child(dir, name).ok_or_else(|| FsError::NotFound {
+                        path: self.join(dir, name),
+                    }),
+                }
+            })
+    }
+
+    /// Finds an absolute `path`.
+    #[cfg(test)]
+    pub(crate) fn lookup(&self, path: &str) -> Result<NodeId, FsError> {
+        self.resolve(self.root(), path)
+    }
+
+    fn join(&self, dir: NodeId, name: &str) -> String {
+        match self.path(dir).as_str() {
+            "/" => format!("/{}", name),
+            path => format!("{}/{}", path, name),
+        }
+    }
+
+    fn insert(&mut self, parent: NodeId, name: &str, kind: NodeKind) -> Result<NodeId, FsError> {
+        let id = NodeId(self.nodes.len());
+        let NodeKind::Dir { children } = &mut self.nodes[parent.0].kind else {
+            return Err(FsError::NotADirectory {
+                path: self.path(parent),
+            });
+        };
+        children.insert(name.to_string(), id);
+        self.nodes.push(Node {
+            name: name.to_string(),
+            parent: Some(parent),
+            kind,
+            size: Cell::new(None),
+        });
+        self.invalidate(parent);
+        Ok(id)
+    }
+
+    /// Forgets the cached sizes of `dir` and everything above it.
+    fn invalidate(&self, dir: NodeId) {
+        for id in self.ancestors(dir) {
+            self.node(id).size.set(None);
+        }
+    }
+
+    /// Adds a directory called `name` in `parent`, or finds the one already there.
+    pub(crate) fn add_dir(&mut self, parent: NodeId, name: &str) -> Result<NodeId, FsError> {
+        match self.child(parent, name) {
+            Some(id) if self.is_dir(id) => Ok(id),
+            Some(_) => Err(FsError::AlreadyExists {
+                path: self.join(parent, name),
+            }),
+            None => self.insert(
+                parent,
+                name,
+                NodeKind::Dir {
+                    children: BTreeMap::new(),
+                },
+            ),
+        }
+    }
+
+    /// Adds a file called `name` in `parent`, or updates the size of the one
+    /// already there. Fails rather than let the total size overflow.
+    pub(crate) fn add_file(
+        &mut self,
+        parent: NodeId,
+        name: &str,
+        size: u64,
+    ) -> Result<NodeId, FsError> {
+        let existing = self.child(parent, name);
+        if matches!(existing, Some(id) if self.is_dir(id)) {
+            return Err(FsError::AlreadyExists {
+                path: self.join(parent, name),
+            });
+        }
+        let replaced = existing.map_or(0, |id| self.size(id));
+        self.total =
+            (self.total - replaced)
+                .checked_add(size)
+                .ok_or_else(|| FsError::TooLarge {
+                    path: self.join(parent, name),
+                })?;
+
+        match existing {
+            Some(id) => {
+                self.nodes[id.0].kind = NodeKind::File { size };
+                self.invalidate(parent);
+                Ok(id)
+            }
+            None => self.insert(parent, name, NodeKind::File { size }),
+        }
+    }
+
+    /// A file's size, or everything under a directory. Directory sizes are
+    /// cached until something below them changes.
+    pub(crate) fn size(&self, id: NodeId) -> u64 {
+        let node = self.node(id);
+        match &node.kind {
+            NodeKind::File { size } => *size,
+            NodeKind::Dir { children } => {
+                if let Some(size) = node.size.get() {
+                    return size;
+                }
+                let size = children.values().map(|&child| self.size(child)).sum();
+                node.size.set(Some(size));
+                size
+            }
+        }
+    }
+
+    /// Every node, parents before their children and siblings in name order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack = vec![self.root()];
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            let len = stack.len();
+            stack.extend(self.children(id));
+            stack[len..].reverse();
+            Some(id)
+        })
+    }
+
+    pub(crate) fn dirs(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.iter().filter(|&id| self.is_dir(id))
+    }
+
+    pub(crate) fn files(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.iter().filter(|&id| !self.is_dir(id))
+    }
+
+    /// Copies the directories and regular files under `path` on disk, with
+    /// file sizes in bytes. Symlinks and other special files are left out, and
+    /// names that aren't UTF-8 are converted lossily. Only failing to read
+    /// `path` itself is an error: anything below it that can't be read, or
+    /// whose converted name is already taken, is skipped with a warning that
+    /// gives its path on disk.
+    pub(crate) fn import(path: &Path) -> io::Result<(Self, Vec<String>)> {
+        let mut fs = Self::default();
+        let mut warnings = vec![];
+        let mut pending: Vec<(NodeId, PathBuf)> = vec![(fs.root(), path.to_path_buf())];
+        while let Some((dir, path)) = pending.pop() {
+            let entries = match std::fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) if dir == fs.root() => return Err(e),
+                Err(e) => {
+                    warnings.push(format!("skipped {}: {}", path.display(), e));
+                    continue;
+                }
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warnings.push(format!("skipped an entry in {}: {}", path.display(), e));
+                        continue;
+                    }
+                };
+                let source = entry.path();
+                let file_name = entry.file_name();
+                let name = file_name.to_string_lossy();
+                if fs.child(dir, &name).is_some() {
+                    warnings.push(format!(
+                        "skipped {}: {} is already taken",
+                        source.display(),
+                        fs.join(dir, &name)
+                    ));
+                    continue;
+                }
+                let added = entry
+                    .metadata()
+                    .map_err(|e| e.to_string())
+                    .and_then(|metadata| {
+                        if metadata.is_dir() {
+                            fs.add_dir(dir, &name)
+                                .map(|id| pending.push((id, source.clone())))
+                        } else if metadata.is_file() {
+                            fs.add_file(dir, &name, metadata.len()).map(|_| ())
+                        } else {
+                            Ok(())
+                        }
+                        .map_err(|e| e.to_string())
+                    });
+                match added {
+                    Err(e) => warnings.push(format!("skipped {}: {}", source.display(), e)),
+                    Ok(()) if file_name.to_str().is_none() => warnings.push(format!(
+                        "imported {} as {}",
+                        source.display(),
+                        fs.join(dir, &name)
+                    )),
+                    Ok(()) => {}
+                }
+            }
+        }
+        Ok((fs, warnings))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::*;
+
+    /// `/a/e/i`, `/a/f`, `/b.txt` and an empty `/d`.
+    fn sample() -> FileSystem {
+        let mut fs = FileSystem::default();
+        let root = fs.root();
+        let a = fs.add_dir(root, "a").unwrap();
+        let e = fs.add_dir(a, "e").unwrap();
+        fs.add_file(e, "i", 584).unwrap();
+        fs.add_file(a, "f", 29_116).unwrap();
+        fs.add_file(root, "b.txt", 14_848_514).unwrap();
+        fs.add_dir(root, "d").unwrap();
+        fs
+    }
+
+    #[rstest(
+        cwd,
+        path,
+        expected,
+        case("/", "a/e/i", Ok("/a/e/i")),
+        case("/a/e", "..", Ok("/a")),
+        case("/a/e", "../../b.txt", Ok("/b.txt")),
+        case("/a/e", "/d", Ok("/d")),
+        case("/a", "./e/../f", Ok("/a/f")),
+        case("/", "..", Ok("/")),
+        case("/", "/", Ok("/")),
+        case("/a", "x", Err(FsError::NotFound { path: "/a/x".to_string() })),
+        case("/", "b.txt/x", Err(FsError::NotADirectory { path: "/b.txt".to_string() }))
+    )]
+    fn test_resolve(cwd: &str, path: &str, expected: Result<&str, FsError>) {
+        let fs = sample();
+        let cwd = fs.lookup(cwd).unwrap();
+        let resolved = fs.resolve(cwd, path).map(|id| fs.path(id));
+        assert_eq!(resolved, expected.map(str::to_string));
+    }
+
+    #[test]
+    fn test_parents_and_depth() {
+        let fs = sample();
+        let i = fs.lookup("/a/e/i").unwrap();
+        assert_eq!(fs.depth(i), 3);
+        assert_eq!(fs.name(fs.parent(i).unwrap()), "e");
+        assert_eq!(fs.parent(fs.root()), None);
+    }
+
+    #[test]
+    fn test_iter() {
+        let fs = sample();
+        let paths = fs.iter().map(|id| fs.path(id)).collect::<Vec<_>>();
+        assert_eq!(paths, ["/", "/a", "/a/e", "/a/e/i", "/a/f", "/b.txt", "/d"]);
+        assert_eq!(fs.dirs().count(), 4);
+        assert_eq!(fs.files().count(), 3);
+    }
+
+    #[test]
+    fn test_size_cache() {
+        let mut fs = sample();
+        let e = fs.lookup("/a/e").unwrap();
+        assert_eq!(fs.size(fs.root()), 14_878_214);
+        assert_eq!(fs.size(e), 584);
+
+        fs.add_file(e, "j", 16).unwrap();
+        fs.add_file(fs.root(), "b.txt", 14).unwrap();
+        assert_eq!((fs.size(e), fs.size(fs.root())), (600, 29_730));
+    }
+
+    #[test]
+    fn test_kind_conflicts() {
+        let mut fs = sample();
+        let root = fs.root();
+        assert_eq!(
+            fs.add_dir(root, "b.txt"),
+            Err(FsError::AlreadyExists {
+                path: "/b.txt".to_string()
+            })
+        );
+        assert!(fs.add_file(root, "a", 1).is_err());
+        let b = fs.lookup("/b.txt").unwrap();
+        assert!(fs.add_file(b, "x", 1).is_err());
+        assert_eq!(fs.add_dir(root, "a"), fs.lookup("/a"));
+    }
+
+    #[test]
+    fn test_import() {
+        let root = std::env::temp_dir().join(format!("filesystem-import-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a/e")).unwrap();
+        std::fs::create_dir_all(root.join("d")).unwrap();
+        std::fs::write(root.join("a/e/i"), [0; 584]).unwrap();
+        std::fs::write(root.join("a/f"), "29116").unwrap();
+        std::fs::write(root.join("b.txt"), "").unwrap();
+
+        let (fs, warnings) = FileSystem::import(&root).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(warnings.is_empty());
+        let sizes = fs
+            .iter()
+            .map(|id| (fs.path(id), fs.size(id)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [
+                ("/".to_string(), 589),
+                ("/a".to_string(), 589),
+                ("/a/e".to_string(), 584),
+                ("/a/e/i".to_string(), 584),
+                ("/a/f".to_string(), 5),
+                ("/b.txt".to_string(), 0),
+                ("/d".to_string(), 0)
+            ]
+        );
+        assert!(FileSystem::import(&root).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_import_lossy_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let root = std::env::temp_dir().join(format!("filesystem-lossy-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(OsStr::from_bytes(b"a\xfe")), "1").unwrap();
+        std::fs::write(root.join(OsStr::from_bytes(b"a\xff")), "22").unwrap();
+
+        let (fs, mut warnings) = FileSystem::import(&root).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        let files = fs.files().map(|id| fs.path(id)).collect::<Vec<_>>();
+        assert_eq!(files, ["/a\u{fffd}"]);
+        warnings.sort();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("imported ") && warnings[0].ends_with(" as /a\u{fffd}"));
+        assert!(
+            warnings[1].starts_with("skipped ")
+                && warnings[1].ends_with(": /a\u{fffd} is already taken")
+        );
+    }
+
+    #[test]
+    fn test_total_overflow() {
+        let mut fs = sample();
+        let root = fs.root();
+        let total = fs.size(root);
+        assert_eq!(
+            fs.add_file(root, "huge", u64::MAX - total + 1),
+            Err(FsError::TooLarge {
+                path: "/huge".to_string()
+            })
+        );
+        assert_eq!(fs.size(root), total);
+        assert!(fs.child(root, "huge").is_none());
+
+        // Replacing a file only counts its new size.
+        fs.add_file(root, "huge", u64::MAX - total).unwrap();
+        fs.add_file(root, "huge", u64::MAX - total).unwrap();
+        assert_eq!(fs.size(root), u64::MAX);
+    }
+}
@@ -0,0 +1,582 @@
+// Inclusive integer intervals and normalized sets of them, shared between puzzles.
+
+use std::{
+    fmt::{Debug, Display},
+    iter::FromIterator,
+    num::ParseIntError,
+    ops::{Range, RangeInclusive},
+    str::FromStr,
+};
+
+/// The integer types an `Interval` can be built over.
+pub(crate) trait Section:
+    Copy + Ord + Debug + Display + FromStr<Err = ParseIntError>
+{
+    fn checked_succ(self) -> Option<Self>;
+    fn checked_pred(self) -> Option<Self>;
+    fn to_i128(self) -> i128;
+    fn from_i128(value: i128) -> Self;
+}
+
+macro_rules! impl_section {
+    ($($t:ty),*) => {
+        $(
+            impl Section for $t {
+                fn checked_succ(self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn checked_pred(self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+
+                fn to_i128(self) -> i128 {
+                    self as i128
+                }
+
+                fn from_i128(value: i128) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_section!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum IntervalError {
+    MissingBound { input: String },
+    InvalidBound { bound: String, error: ParseIntError },
+    Reversed { lo: String, hi: String },
+    Empty { lo: String },
+}
+
+impl Display for IntervalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBound { input } => {
+                write!(f, "expected `lo-hi`, `lo..=hi` or `lo..hi`: {:?}", input)
+            }
+            Self::InvalidBound { bound, error } => {
+                write!(f, "invalid bound {:?}: {}", bound, error)
+            }
+            Self::Reversed { lo, hi } => write!(f, "bounds reversed: {} is after {}", lo, hi),
+            Self::Empty { lo } => write!(f, "half-open interval starting at {} is empty", lo),
+        }
+    }
+}
+
+impl std::error::Error for IntervalError {}
+
+/// A non-empty run of sections, stored with both bounds inclusive. Half-open
+/// bounds are accepted by `half_open` and `lo..hi`, and converted on the way in.
+/// The bounds are only set through the constructors, so `lo <= hi` always holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Interval<T = u32> {
+    lo: T,
+    hi: T,
+}
+
+fn parse_bound<T: Section>(bound: &str) -> Result<T, IntervalError> {
+    bound
+        .trim()
+        .parse::<T>()
+        .map_err(|error| IntervalError::InvalidBound {
+            bound: bound.to_string(),
+            error,
+        })
+}
+
+/// Parses `lo-hi` (the puzzle's format) or `lo..=hi` as inclusive, and `lo..hi`
+/// as half-open. A leading `-` is read as a sign, so `-3--1` works for signed types.
+impl<T: Section> FromStr for Interval<T> {
+    type Err = IntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let missing = || IntervalError::MissingBound {
+            input: s.to_string(),
+        };
+
+        if let Some((lo, hi)) = s.split_once("..=") {
+            Self::inclusive(parse_bound(lo)?, parse_bound(hi)?)
+        } else if let Some((lo, hi)) = s.split_once("..") {
+            Self::half_open(parse_bound(lo)?, parse_bound(hi)?)
+        } else {
+            let split = s
+                .get(1..)
+                .and_then(|rest| rest.find('-'))
+                .ok_or_else(missing)?
+                + 1;
+            let (lo, hi) = (&s[..split], &s[split + 1..]);
+            if lo.trim().is_empty() || hi.trim().is_empty() {
+                return Err(missing());
+            }
+            Self::inclusive(parse_bound(lo)?, parse_bound(hi)?)
+        }
+    }
+}
+
+impl<T: Section> Display for Interval<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.lo == self.hi {
+            write!(f, "{}", self.lo)
+        } else {
+            write!(f, "{}-{}", self.lo, self.hi)
+        }
+    }
+}
+
+impl<T: Section> From<Interval<T>> for RangeInclusive<T> {
+    fn from(interval: Interval<T>) -> Self {
+        interval.lo()..=interval.hi()
+    }
+}
+
+impl<T: Section> TryFrom<RangeInclusive<T>> for Interval<T> {
+    type Error = IntervalError;
+
+    fn try_from(range: RangeInclusive<T>) -> Result<Self, Self::Error> {
+        let (lo, hi) = range.into_inner();
+        Self::inclusive(lo, hi)
+    }
+}
+
+impl<T: Section> TryFrom<Range<T>> for Interval<T> {
+    type Error = IntervalError;
+
+    fn try_from(range: Range<T>) -> Result<Self, Self::Error> {
+        Self::half_open(range.start, range.end)
+    }
+}
+
+impl<T: Section> Interval<T> {
+    /// Panics on reversed bounds; use `inclusive` to get an error instead.
+    pub(crate) fn new(lo: T, hi: T) -> Self {
+        Self::inclusive(lo, hi).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// `lo` up to and including `hi`.
+    pub(crate) fn inclusive(lo: T, hi: T) -> Result<Self, IntervalError> {
+        if lo > hi {
+            return Err(IntervalError::Reversed {
+                lo: lo.to_string(),
+                hi: hi.to_string(),
+            });
+        }
+        Ok(Self { lo, hi })
+    }
+
+    /// `lo` up to but not including `end`.
+    pub(crate) fn half_open(lo: T, end: T) -> Result<Self, IntervalError> {
+        if lo > end {
+            return Err(IntervalError::Reversed {
+                lo: lo.to_string(),
+                hi: end.to_string(),
+            });
+        }
+        match end.checked_pred() {
+            Some(hi) if lo <= hi => Ok(Self { lo, hi }),
+            _ => Err(IntervalError::Empty { lo: lo.to_string() }),
+        }
+    }
+
+    /// The lowest section covered.
+    pub(crate) fn lo(&self) -> T {
+        self.lo
+    }
+
+    /// The highest section covered.
+    pub(crate) fn hi(&self) -> T {
+        self.hi
+    }
+
+    /// The exclusive upper bound, unless `hi` is already the type's maximum.
+    pub(crate) fn end(&self) -> Option<T> {
+        self.hi.checked_succ()
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn to_half_open(self) -> Option<Range<T>> {
+        Some(self.lo()..self.end()?)
+    }
+
+    pub(crate) fn len(&self) -> u128 {
+        (self.hi.to_i128() - self.lo.to_i128()) as u128 + 1
+    }
+
+    pub(crate) fn includes(&self, other: &Self) -> bool {
+        self.lo <= other.lo && self.hi >= other.hi
+    }
+
+    pub(crate) fn overlaps(&self, other: &Self) -> bool {
+        self.lo <= other.hi && self.hi >= other.lo
+    }
+
+    /// Overlapping, or directly next to each other with no section between.
+    pub(crate) fn touches(&self, other: &Self) -> bool {
+        self.overlaps(other)
+            || self.hi.checked_succ() == Some(other.lo)
+            || other.hi.checked_succ() == Some(self.lo)
+    }
+
+    pub(crate) fn intersection(&self, other: &Self) -> Option<Self> {
+        if self.overlaps(other) {
+            Some(Self::new(self.lo.max(other.lo), self.hi.min(other.hi)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Sorts and merges overlapping or touching intervals into disjoint ones.
+pub(crate) fn merge_overlapping<T, I>(intervals: I) -> Vec<Interval<T>>
+where
+    T: Section,
+    I: IntoIterator<Item = Interval<T>>,
+{
+    let mut intervals = intervals.into_iter().collect::<Vec<_>>();
+    intervals.sort();
+
+    let mut merged: Vec<Interval<T>> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if last.touches(&interval) => last.hi = last.hi.max(interval.hi),
+            _ => merged.push(interval),
+        }
+    }
+
+    merged
+}
+
+/// Index pairs `(i, j)`, `i < j`, of every two intervals that overlap. Sweeps
+/// the intervals by low bound, so it costs `O(n log n)` plus one step per pair.
+pub(crate) fn overlapping_pairs<T: Section>(intervals: &[Interval<T>]) -> Vec<(usize, usize)> {
+    let mut order = (0..intervals.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| intervals[i].lo());
+
+    let mut active: Vec<usize> = vec![];
+    let mut pairs = vec![];
+    for i in order {
+        let lo = intervals[i].lo();
+        active.retain(|&j| intervals[j].hi() >= lo);
+        pairs.extend(active.iter().map(|&j| (i.min(j), i.max(j))));
+        active.push(i);
+    }
+
+    pairs.sort_unstable();
+    pairs
+}
+
+/// The most intervals covering any single value, and every value covered that
+/// many times.
+pub(crate) fn max_depth<T: Section>(intervals: &[Interval<T>]) -> (usize, IntervalSet<T>) {
+    let mut events = intervals
+        .iter()
+        .flat_map(|interval| {
+            [
+                (interval.lo().to_i128(), 1),
+                (interval.hi().to_i128() + 1, -1),
+            ]
+        })
+        .collect::<Vec<(i128, i64)>>();
+    events.sort_unstable();
+
+    let (mut depth, mut max) = (0, 0);
+    let mut deepest = vec![];
+    for (i, &(position, delta)) in events.iter().enumerate() {
+        depth += delta;
+        match events.get(i + 1) {
+            Some(&(next, _)) if next > position && depth > 0 => {
+                if depth > max {
+                    max = depth;
+                    deepest.clear();
+                }
+                if depth == max {
+                    deepest.push(Interval::new(
+                        T::from_i128(position),
+                        T::from_i128(next - 1),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (max as usize, deepest.into_iter().collect())
+}
+
+/// A set of sections, kept as sorted, disjoint and non-touching intervals.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct IntervalSet<T = u32>(Vec<Interval<T>>);
+
+impl<T: Section> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        Self(merge_overlapping(iter))
+    }
+}
+
+impl<T: Section> Display for IntervalSet<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let intervals = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{}", intervals)
+    }
+}
+
+impl<T: Section> IntervalSet<T> {
+    /// Total number of sections covered.
+    pub(crate) fn len(&self) -> u128 {
+        self.0.iter().map(Interval::len).sum()
+    }
+
+    /// The smallest interval covering the whole set.
+    pub(crate) fn bounds(&self) -> Option<Interval<T>> {
+        Some(Interval::new(self.0.first()?.lo(), self.0.last()?.hi()))
+    }
+
+    /// Sections between the lowest and highest covered ones that aren't covered.
+    pub(crate) fn gaps(&self) -> Self {
+        Self(
+            self.0
+                .windows(2)
+                .map(|pair| {
+                    Interval::new(
+                        pair[0].hi().checked_succ().unwrap(),
+                        pair[1].lo().checked_pred().unwrap(),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Set algebra for later puzzles; no day combines sets this way yet.
+#[allow(dead_code)]
+impl<T: Section> IntervalSet<T> {
+    pub(crate) fn union(&self, other: &Self) -> Self {
+        self.0.iter().chain(other.0.iter()).copied().collect()
+    }
+
+    pub(crate) fn intersection(&self, other: &Self) -> Self {
+        let (mut i, mut j) = (0, 0);
+        let mut intersection = vec![];
+
+        while let (Some(a), Some(b)) = (self.0.get(i), other.0.get(j)) {
+            intersection.extend(a.intersection(b));
+            if a.hi < b.hi {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        Self(intersection)
+    }
+
+    pub(crate) fn difference(&self, other: &Self) -> Self {
+        let mut difference = vec![];
+        let mut j = 0;
+
+        for interval in &self.0 {
+            let mut lo = Some(interval.lo);
+
+            while let Some(cut) = other.0.get(j) {
+                if cut.hi < interval.lo {
+                    j += 1;
+                    continue;
+                }
+                if cut.lo > interval.hi {
+                    break;
+                }
+                if let Some(lo) = lo.filter(|&lo| lo < cut.lo) {
+                    difference.push(Interval::new(lo, cut.lo.checked_pred().unwrap()));
+                }
+                lo = cut.hi.checked_succ();
+                if cut.hi > interval.hi {
+                    break;
+                }
+                j += 1;
+            }
+
+            if let Some(lo) = lo.filter(|&lo| lo <= interval.hi) {
+                difference.push(Interval::new(lo, interval.hi));
+            }
+        }
+
+        Self(difference)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::*;
+
+    fn set(s: &str) -> IntervalSet {
+        s.split(',')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse::<Interval>().unwrap())
+            .collect()
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("5-7,1-3,2-4", "1-7"),
+        case("1-2,3-5", "1-5"),
+        case("1-2,5-6,3-3", "1-3, 5-6"),
+        case("4-4,9-9,1-1", "1, 4, 9"),
+        case("", "")
+    )]
+    fn test_merge_overlapping(input: &str, expected: &str) {
+        assert_eq!(set(input).to_string(), expected);
+    }
+
+    #[rstest(
+        a,
+        b,
+        union,
+        intersection,
+        difference,
+        case("1-10", "3-4,8-12", "1-12", "3-4,8-10", "1-2,5-7"),
+        case("1-3,7-9", "4-6", "1-9", "", "1-3,7-9"),
+        case("2-8", "1-9", "1-9", "2-8", ""),
+        case("0-4294967295", "0-0", "0-4294967295", "0-0", "1-4294967295")
+    )]
+    fn test_set_operations(a: &str, b: &str, union: &str, intersection: &str, difference: &str) {
+        let (a, b) = (set(a), set(b));
+        assert_eq!(a.union(&b), set(union));
+        assert_eq!(a.intersection(&b), set(intersection));
+        assert_eq!(a.difference(&b), set(difference));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("1-3,2-5,4-4,6-8", vec![(0, 1), (1, 2)]),
+        case("1-1,1-1,1-1", vec![(0, 1), (0, 2), (1, 2)]),
+        case("1-2,3-4", vec![])
+    )]
+    fn test_overlapping_pairs(input: &str, expected: Vec<(usize, usize)>) {
+        let intervals = input
+            .split(',')
+            .map(|part| part.parse::<Interval>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(overlapping_pairs(&intervals), expected);
+    }
+
+    #[rstest(
+        input,
+        depth,
+        deepest,
+        case("1-3,2-5,4-4,6-8", 2, "2-4"),
+        case("1-10,3-3,7-7,2-8", 3, "3, 7"),
+        case("0-4294967295,4294967295-4294967295", 2, "4294967295"),
+        case("", 0, "")
+    )]
+    fn test_max_depth(input: &str, depth: usize, deepest: &str) {
+        let intervals = input
+            .split(',')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse::<Interval>().unwrap())
+            .collect::<Vec<_>>();
+        let (found_depth, found_deepest) = max_depth(&intervals);
+        assert_eq!(
+            (found_depth, found_deepest.to_string()),
+            (depth, deepest.to_string())
+        );
+    }
+
+    #[rstest(
+        input,
+        len,
+        gaps,
+        case("2-4,6-8,12-12", 7, "5-5,9-11"),
+        case("", 0, "")
+    )]
+    fn test_coverage(input: &str, len: u128, gaps: &str) {
+        let intervals = set(input);
+        assert_eq!(intervals.len(), len);
+        assert_eq!(intervals.gaps(), set(gaps));
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case("2-4", Ok((2, 4))),
+        case("-3--1", Ok((-3, -1))),
+        case("-3-5", Ok((-3, 5))),
+        case("2..=4", Ok((2, 4))),
+        case("2..5", Ok((2, 4))),
+        case("4-2", Err(IntervalError::Reversed { lo: "4".to_string(), hi: "2".to_string() })),
+        case("3..3", Err(IntervalError::Empty { lo: "3".to_string() })),
+        case("3", Err(IntervalError::MissingBound { input: "3".to_string() })),
+        case("3-", Err(IntervalError::MissingBound { input: "3-".to_string() }))
+    )]
+    fn test_parse_signed(input: &str, expected: Result<(i64, i64), IntervalError>) {
+        assert_eq!(
+            input.parse::<Interval<i64>>(),
+            expected.map(|(lo, hi)| Interval::inclusive(lo, hi).unwrap())
+        );
+    }
+
+    #[rstest(input, case("a-4"), case("300-301"), case("-1-2"))]
+    fn test_parse_invalid_bound(input: &str) {
+        assert!(matches!(
+            input.parse::<Interval<u8>>(),
+            Err(IntervalError::InvalidBound { .. })
+        ));
+    }
+
+    #[rstest(lo, hi, end, case(2, 4, 5), case(0, 0, 1), case(0, 254, 255))]
+    fn test_constructors(lo: u8, hi: u8, end: u8) {
+        let interval = Interval::inclusive(lo, hi).unwrap();
+        assert_eq!(Interval::half_open(lo, end), Ok(interval));
+        assert_eq!((interval.lo(), interval.hi()), (lo, hi));
+        assert_eq!(interval.end(), Some(end));
+    }
+
+    #[test]
+    fn test_constructor_errors() {
+        assert_eq!(
+            Interval::inclusive(5u8, 4),
+            Err(IntervalError::Reversed {
+                lo: "5".to_string(),
+                hi: "4".to_string()
+            })
+        );
+        assert_eq!(
+            Interval::half_open(5u8, 4),
+            Err(IntervalError::Reversed {
+                lo: "5".to_string(),
+                hi: "4".to_string()
+            })
+        );
+        assert_eq!(
+            Interval::half_open(3u8, 3),
+            Err(IntervalError::Empty {
+                lo: "3".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_range_conversions() {
+        let interval = Interval::<u8>::try_from(3..=255).unwrap();
+        assert_eq!(RangeInclusive::from(interval), 3..=255);
+        assert_eq!(interval.to_half_open(), None);
+        assert_eq!(interval.len(), 253);
+        assert_eq!(
+            Interval::try_from(3u8..7).unwrap().to_half_open(),
+            Some(3..7)
+        );
+        let (start, end) = (7u8, 3u8);
+        assert!(Interval::try_from(start..end).is_err());
+        assert_eq!(Interval::<i8>::new(-128, 127).len(), 256);
+    }
+}
@@ -1,34 +1,69 @@
-#![feature(iter_array_chunks)]
+#![cfg_attr(test, feature(test))]
 
 use std::{env, fs};
 
-use miette::Result;
+use miette::{miette, IntoDiagnostic, Result};
 
+use crate::cli::has_flag;
+
+mod cli;
+mod filesystem;
+mod interval;
+#[cfg(test)]
+mod rng;
 // mod day_01;
-// mod day_02;
-// mod day_03;
-// mod day_04;
-// mod day_05;
-// mod day_06;
-// mod day_07;
+mod day_02;
+mod day_03;
+mod day_04;
+mod day_05;
+mod day_06;
+mod day_07;
 // mod day_08;
 // mod day_09;
 mod day_10;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let input = env::args().nth(1).map(fs::read_to_string).unwrap().unwrap();
-
-    // println!("{}", day_01::part_1_and_2().await?);
-    // println!("{}", day_02::part_1_and_2());
-    // println!("{}", day_03::part_1());
-    // println!("{}", day_04::solve());
-    // println!("{}", day_05::solve());
-    // println!("{}", day_06::solve(input));
-    // println!("{}", day_07::solve(input));
-    // println!("{}", day_08::solve(input));
-    // println!("{}", day_09::solve(input));
-    println!("{}", day_10::solve(input));
+    let mut args = env::args().skip(1);
+    let day = args
+        .next()
+        .ok_or_else(|| miette!("usage: advent-of-code <day> <input> [options]"))?
+        .parse::<u32>()
+        .into_diagnostic()?;
+    let source = args
+        .next()
+        .ok_or_else(|| miette!("expected an input file"))?;
+    let options = args.collect::<Vec<_>>();
+
+    // Live modes read their input as it arrives rather than all up front.
+    if day == 6 && (has_flag(&options, "--tail") || has_flag(&options, "--connect")) {
+        println!("{}", day_06::live(&source, &options).await?);
+        return Ok(());
+    }
+
+    // With --import, day 7's input is a real directory rather than a transcript.
+    if day == 7 && has_flag(&options, "--import") {
+        println!("{}", day_07::import(&source, &options)?);
+        return Ok(());
+    }
+
+    let input = fs::read_to_string(source).into_diagnostic()?;
+
+    let output = match day {
+        // 1 => day_01::part_1_and_2().await?,
+        2 => day_02::part_1_and_2(input, &options)?,
+        3 => day_03::part_1(input, &options)?,
+        4 => day_04::solve(input, &options)?,
+        5 => day_05::solve(input, &options)?,
+        6 => day_06::solve(input, &options)?,
+        7 => day_07::solve(input, &options)?,
+        // 8 => day_08::solve(input),
+        // 9 => day_09::solve(input),
+        10 => day_10::solve(input),
+        _ => return Err(miette!("day {} is not enabled", day)),
+    };
+
+    println!("{}", output);
 
     Ok(())
 }
@@ -0,0 +1,36 @@
+// A small deterministic random number generator, so tests and benchmarks can
+// generate the same large inputs on every run.
+
+/// Marsaglia's xorshift64. Not for anything that needs real randomness.
+pub(crate) struct XorShift(u64);
+
+impl Default for XorShift {
+    fn default() -> Self {
+        Self::new(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl XorShift {
+    /// A generator started from `seed`, which must not be zero.
+    pub(crate) fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift never leaves a zero state");
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..bound`.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// An index into something of length `len`.
+    pub(crate) fn index(&mut self, len: usize) -> usize {
+        self.below(len as u64) as usize
+    }
+}